@@ -38,7 +38,7 @@ async fn main() {
                         let prefix = if args.len() < 2 { "" } else { args[1] };
 
                         println!("Получаем список...");
-                        let res = client.get_files(prefix).await;
+                        let res = client.get_files(None, prefix).await;
 
                         match res {
                             Ok(files) => {
@@ -70,7 +70,7 @@ async fn main() {
 
                         match args[1] {
                             "-s" => {
-                                let res = client.create_small_file(args[2], file).await;
+                                let res = client.create_small_file(args[2], &[], file).await;
 
                                 if let Err(err) = res {
                                     println!("Ошибка: {:?}", err)
@@ -79,7 +79,7 @@ async fn main() {
                                 }
                             }
                             "-l" => {
-                                let res = client.create_large_file(args[2], file).await;
+                                let res = client.create_large_file(args[2], &[], file).await;
 
                                 if let Err(err) = res {
                                     println!("Ошибка: {:?}", err)
@@ -112,13 +112,13 @@ async fn main() {
                             "-s" => {
                                 let res = if let Some(index) = args.get(4) {
                                     if let Ok(index) = index.parse::<usize>() {
-                                        client.read_small_file(args[2], index).await
+                                        client.read_small_file(None, args[2], index).await
                                     } else {
                                         println!("Неверный формат версии");
                                         continue;
                                     }
                                 } else {
-                                    client.read_small_file_last_version(args[2]).await
+                                    client.read_small_file_last_version(None, args[2]).await
                                 };
 
                                 match res {
@@ -132,12 +132,14 @@ async fn main() {
                                 }
                             }
                             "-l" => {
-                                let res = client.read_large_file(args[2]).await;
+                                let res = client.large_file_reader(None, args[2]).await;
 
                                 match res {
-                                    Ok(bytes) => {
-                                        let _ = file.write_all(&bytes).await;
-                                        println!("Успешно скачано");
+                                    Ok(mut reader) => {
+                                        match tokio::io::copy(&mut reader, &mut file).await {
+                                            Ok(_) => println!("Успешно скачано"),
+                                            Err(err) => println!("Ошибка: {}", err),
+                                        }
                                     }
                                     Err(err) => {
                                         println!("Ошибка: {:?}", err)
@@ -170,7 +172,9 @@ async fn main() {
 
                         println!("Обновление...");
 
-                        let res = client.add_new_commit_to_small_file(args[1], &buffer).await;
+                        let res = client
+                            .add_new_commit_to_small_file(None, args[1], &buffer)
+                            .await;
 
                         if let Err(err) = res {
                             println!("Ошибка: {:?}", err)
@@ -185,7 +189,7 @@ async fn main() {
                         }
 
                         println!("Удаление...");
-                        let res = client.delete_file(args[1]).await;
+                        let res = client.delete_file(None, args[1]).await;
 
                         if let Err(err) = res {
                             println!("Ошибка: {:?}", err)
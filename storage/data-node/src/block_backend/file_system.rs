@@ -0,0 +1,197 @@
+use super::{BlockBackend, StoredBlock};
+use crate::data_node_info::DataNodeInfo;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use shared::data_node_error::DataNodeError;
+use shared::hash::{Checksum, HashAlgorithm};
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter};
+use uuid::Uuid;
+
+/// Keeps every block in a `<uuid as u128>_<part>` file in the working directory
+/// of one of the disks.
+pub struct FileSystemBackend {
+    data_node_info: Arc<DataNodeInfo>,
+}
+
+impl FileSystemBackend {
+    pub async fn new(data_node_info: Arc<DataNodeInfo>) -> std::io::Result<Self> {
+        for disk in &data_node_info.space.disks {
+            let path = disk.mount.join(&data_node_info.working_directory);
+            if !path.exists() {
+                tokio::fs::create_dir(path).await?;
+            }
+        }
+
+        Ok(Self { data_node_info })
+    }
+
+    async fn find(&self, block_id: Uuid, part: usize) -> Result<PathBuf, DataNodeError> {
+        self.data_node_info.found_block(block_id, part).await
+    }
+}
+
+#[async_trait]
+impl BlockBackend for FileSystemBackend {
+    async fn create(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
+        // The block keeps its disk and its space is reserved once
+        if self.find(block_id, part).await.is_ok() {
+            return Ok(());
+        }
+
+        let disk = self.data_node_info.reserve_block(block_id, part).await?;
+        let path = self.data_node_info.block_path(disk, block_id, part);
+
+        // A file left by a block which was not released is reused empty
+        let file = OpenOptions::new()
+            .write(true)
+            .read(false)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await;
+
+        if let Err(err) = file {
+            self.data_node_info.release_block(block_id, part).await;
+            return Err(DataNodeError::CreateBlockError(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn read_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, DataNodeError> {
+        let path = self.find(block_id, part).await?;
+
+        let file = OpenOptions::new()
+            .write(false)
+            .read(true)
+            .open(path)
+            .await
+            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?;
+        let mut reader = BufReader::new(file);
+        let mut buffer = vec![0; range.len()];
+        reader
+            .seek(SeekFrom::Start(range.start as u64))
+            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))
+            .await?;
+        reader
+            .read_exact(&mut buffer)
+            .await
+            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?;
+
+        Ok(buffer)
+    }
+
+    async fn write_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), DataNodeError> {
+        let path = self.find(block_id, part).await?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .read(false)
+            .open(&path)
+            .await
+            .map_err(|_| DataNodeError::UpdateBlockError(block_id.to_string()))?;
+
+        let mut writer = BufWriter::new(file);
+
+        writer
+            .seek(SeekFrom::Start(offset as u64))
+            .await
+            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+        writer
+            .write_all(data)
+            .await
+            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+        writer
+            .flush()
+            .await
+            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
+        let path = self.find(block_id, part).await?;
+
+        tokio::fs::remove_file(path)
+            .await
+            .map_err(|_| DataNodeError::DeleteBlockError(block_id.to_string()))?;
+        self.data_node_info.release_block(block_id, part).await;
+
+        Ok(())
+    }
+
+    async fn size(&self, block_id: Uuid, part: usize) -> Result<usize, DataNodeError> {
+        let path = self.find(block_id, part).await?;
+
+        Ok(path
+            .metadata()
+            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?
+            .len() as usize)
+    }
+
+    async fn checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        let path = self.find(block_id, part).await?;
+
+        let file = OpenOptions::new()
+            .write(false)
+            .read(true)
+            .open(&path)
+            .await
+            .map_err(|_| DataNodeError::UpdateBlockError(block_id.to_string()))?;
+
+        let mut reader = BufReader::new(file);
+        let mut buffer = vec![];
+        let _ = reader
+            .read_to_end(&mut buffer)
+            .await
+            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+
+        Ok(hash_algorithm.hash(&buffer))
+    }
+
+    async fn list(&self) -> Result<Vec<StoredBlock>, DataNodeError> {
+        Ok(self
+            .data_node_info
+            .block_index
+            .blocks()
+            .await
+            .into_iter()
+            .map(|((block_id, part), entry)| StoredBlock {
+                block_id,
+                part,
+                modified: entry.modified,
+            })
+            .collect())
+    }
+}
+
+#[cfg(any(test, bench))]
+impl Drop for FileSystemBackend {
+    fn drop(&mut self) {
+        for disk in &self.data_node_info.space.disks {
+            let _ =
+                std::fs::remove_dir_all(disk.mount.join(&self.data_node_info.working_directory));
+        }
+    }
+}
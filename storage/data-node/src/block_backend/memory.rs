@@ -0,0 +1,145 @@
+use super::{BlockBackend, StoredBlock};
+use async_trait::async_trait;
+use shared::data_node_error::DataNodeError;
+use shared::hash::{Checksum, HashAlgorithm};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+struct MemoryBlock {
+    data: Vec<u8>,
+    modified: u64,
+}
+
+/// Keeps blocks in memory. Nothing touches the disk, so tests using it can run in parallel.
+#[derive(Default)]
+pub struct MemoryBackend {
+    blocks: RwLock<HashMap<(Uuid, usize), MemoryBlock>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn not_found(block_id: Uuid, part: usize) -> DataNodeError {
+    DataNodeError::BlockNotFound(format!("{}_{} not found.", block_id.as_u128(), part))
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[async_trait]
+impl BlockBackend for MemoryBackend {
+    async fn create(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
+        self.blocks
+            .write()
+            .await
+            .entry((block_id, part))
+            .or_insert_with(|| MemoryBlock {
+                data: vec![],
+                modified: unix_time(),
+            });
+
+        Ok(())
+    }
+
+    async fn read_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, DataNodeError> {
+        let blocks = self.blocks.read().await;
+        let block = blocks
+            .get(&(block_id, part))
+            .ok_or_else(|| not_found(block_id, part))?;
+
+        block
+            .data
+            .get(range.clone())
+            .map(|data| data.to_vec())
+            .ok_or_else(|| {
+                DataNodeError::ReadBlockError(format!(
+                    "{:?} is out of block with length {}",
+                    range,
+                    block.data.len()
+                ))
+            })
+    }
+
+    async fn write_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), DataNodeError> {
+        let mut blocks = self.blocks.write().await;
+        let block = blocks
+            .get_mut(&(block_id, part))
+            .ok_or_else(|| not_found(block_id, part))?;
+
+        let end = offset + data.len();
+        if block.data.len() < end {
+            block.data.resize(end, 0);
+        }
+        block.data[offset..end].copy_from_slice(data);
+        block.modified = unix_time();
+
+        Ok(())
+    }
+
+    async fn delete(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
+        self.blocks
+            .write()
+            .await
+            .remove(&(block_id, part))
+            .map(|_| ())
+            .ok_or_else(|| DataNodeError::DeleteBlockError(block_id.to_string()))
+    }
+
+    async fn size(&self, block_id: Uuid, part: usize) -> Result<usize, DataNodeError> {
+        self.blocks
+            .read()
+            .await
+            .get(&(block_id, part))
+            .map(|block| block.data.len())
+            .ok_or_else(|| not_found(block_id, part))
+    }
+
+    async fn checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        self.blocks
+            .read()
+            .await
+            .get(&(block_id, part))
+            .map(|block| hash_algorithm.hash(&block.data))
+            .ok_or_else(|| not_found(block_id, part))
+    }
+
+    async fn list(&self) -> Result<Vec<StoredBlock>, DataNodeError> {
+        Ok(self
+            .blocks
+            .read()
+            .await
+            .iter()
+            .map(|(&(block_id, part), block)| StoredBlock {
+                block_id,
+                part,
+                modified: block.modified,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,56 @@
+pub mod file_system;
+pub mod memory;
+pub mod packed;
+
+use async_trait::async_trait;
+use shared::data_node_error::DataNodeError;
+use shared::hash::{Checksum, HashAlgorithm};
+use std::ops::Range;
+use uuid::Uuid;
+
+/// Block stored by a backend.
+pub struct StoredBlock {
+    pub block_id: Uuid,
+    pub part: usize,
+    /// Unix time in seconds of the last modification
+    pub modified: u64,
+}
+
+/// Storage engine keeping the bytes of block parts. Limits of the block size are
+/// checked by `BlockStorage` before a backend is called.
+#[async_trait]
+pub trait BlockBackend: Send + Sync {
+    /// Creates an empty block.
+    async fn create(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError>;
+
+    /// Reads exactly `range` of the block.
+    async fn read_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, DataNodeError>;
+
+    /// Writes `data` from `offset`, growing the block if needed.
+    async fn write_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), DataNodeError>;
+
+    async fn delete(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError>;
+
+    /// Current length of the block in bytes.
+    async fn size(&self, block_id: Uuid, part: usize) -> Result<usize, DataNodeError>;
+
+    async fn checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError>;
+
+    async fn list(&self) -> Result<Vec<StoredBlock>, DataNodeError>;
+}
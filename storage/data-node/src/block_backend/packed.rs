@@ -0,0 +1,861 @@
+use super::{BlockBackend, StoredBlock};
+use crate::block_index::BlockEntry;
+use crate::data_node_info::DataNodeInfo;
+use async_trait::async_trait;
+use shared::data_node_error::DataNodeError;
+use shared::hash::{Checksum, HashAlgorithm};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+const INDEX_FILE: &str = "index";
+const SEGMENT_PREFIX: &str = "segment_";
+/// op, block id, part, block offset, segment, segment offset, len, modified
+const RECORD_SIZE: usize = 1 + 16 + 8 + 8 + 4 + 8 + 8 + 8;
+/// Segments with less live data than this share of their size are compacted
+const COMPACTION_THRESHOLD: f64 = 0.5;
+
+/// Bytes of a block written at `block_offset`, stored at `offset` of a segment.
+#[derive(Clone, Copy, PartialEq)]
+struct Extent {
+    block_offset: usize,
+    segment: u32,
+    offset: u64,
+    len: usize,
+}
+
+impl Extent {
+    /// Bytes of `range` of the block this extent holds, empty if none.
+    fn overlap(&self, range: &Range<usize>) -> Range<usize> {
+        range.start.max(self.block_offset)..range.end.min(self.block_offset + self.len)
+    }
+}
+
+#[derive(Default)]
+struct PackedBlock {
+    /// Later extents overwrite earlier ones
+    extents: Vec<Extent>,
+    modified: u64,
+}
+
+impl PackedBlock {
+    fn size(&self) -> usize {
+        size(&self.extents)
+    }
+}
+
+fn size(extents: &[Extent]) -> usize {
+    extents
+        .iter()
+        .map(|extent| extent.block_offset + extent.len)
+        .max()
+        .unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Segment {
+    size: u64,
+    /// Bytes referenced by the index, overwritten extents included
+    live: u64,
+}
+
+/// Entry of the append-only index log.
+enum Record {
+    /// Creates an empty block or drops all data of an existing one
+    Create {
+        block: (Uuid, usize),
+        modified: u64,
+    },
+    Write {
+        block: (Uuid, usize),
+        extent: Extent,
+        modified: u64,
+    },
+    Delete {
+        block: (Uuid, usize),
+    },
+}
+
+impl Record {
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let (op, (block_id, part), extent, modified) = match *self {
+            Record::Create { block, modified } => (1u8, block, None, modified),
+            Record::Write {
+                block,
+                extent,
+                modified,
+            } => (2u8, block, Some(extent), modified),
+            Record::Delete { block } => (3u8, block, None, 0),
+        };
+        let extent = extent.unwrap_or(Extent {
+            block_offset: 0,
+            segment: 0,
+            offset: 0,
+            len: 0,
+        });
+
+        let mut buffer = [0; RECORD_SIZE];
+        buffer[0] = op;
+        buffer[1..17].copy_from_slice(block_id.as_bytes());
+        buffer[17..25].copy_from_slice(&(part as u64).to_le_bytes());
+        buffer[25..33].copy_from_slice(&(extent.block_offset as u64).to_le_bytes());
+        buffer[33..37].copy_from_slice(&extent.segment.to_le_bytes());
+        buffer[37..45].copy_from_slice(&extent.offset.to_le_bytes());
+        buffer[45..53].copy_from_slice(&(extent.len as u64).to_le_bytes());
+        buffer[53..61].copy_from_slice(&modified.to_le_bytes());
+
+        buffer
+    }
+
+    fn decode(buffer: &[u8]) -> Option<Self> {
+        let u64_at = |at: usize| u64::from_le_bytes(buffer[at..at + 8].try_into().unwrap());
+
+        let block = (Uuid::from_slice(&buffer[1..17]).ok()?, u64_at(17) as usize);
+        let extent = Extent {
+            block_offset: u64_at(25) as usize,
+            segment: u32::from_le_bytes(buffer[33..37].try_into().unwrap()),
+            offset: u64_at(37),
+            len: u64_at(45) as usize,
+        };
+        let modified = u64_at(53);
+
+        match buffer[0] {
+            1 => Some(Record::Create { block, modified }),
+            2 => Some(Record::Write {
+                block,
+                extent,
+                modified,
+            }),
+            3 => Some(Record::Delete { block }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    blocks: HashMap<(Uuid, usize), PackedBlock>,
+    segments: BTreeMap<u32, Segment>,
+    /// Segment new data is appended to
+    active: u32,
+}
+
+impl State {
+    /// Segments other than the active one with mostly deleted data.
+    fn sparse_segments(&self) -> Vec<u32> {
+        self.segments
+            .iter()
+            .filter(|(&id, segment)| {
+                id != self.active
+                    && (segment.live as f64) < segment.size as f64 * COMPACTION_THRESHOLD
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// Data of the extents is not referenced anymore.
+    fn drop_live(&mut self, extents: &[Extent]) {
+        for extent in extents {
+            if let Some(segment) = self.segments.get_mut(&extent.segment) {
+                segment.live = segment.live.saturating_sub(extent.len as u64);
+            }
+        }
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Create { block, modified } => {
+                let dropped = self.blocks.insert(
+                    block,
+                    PackedBlock {
+                        extents: vec![],
+                        modified,
+                    },
+                );
+                if let Some(dropped) = dropped {
+                    self.drop_live(&dropped.extents);
+                }
+            }
+            Record::Write {
+                block,
+                extent,
+                modified,
+            } => match self.blocks.get_mut(&block) {
+                Some(block) => {
+                    block.extents.push(extent);
+                    block.modified = modified;
+                }
+                // The block was deleted while the data was written
+                None => self.drop_live(&[extent]),
+            },
+            Record::Delete { block } => {
+                if let Some(dropped) = self.blocks.remove(&block) {
+                    self.drop_live(&dropped.extents);
+                }
+            }
+        }
+    }
+}
+
+/// Segments and index log of the blocks placed on one disk.
+struct Store {
+    directory: PathBuf,
+    segment_size: u64,
+    state: Mutex<State>,
+    /// Locked while a record is appended and applied to the state, so records
+    /// are applied in the order they are logged
+    index: Mutex<File>,
+    /// Held for reading while segments are read or written. Compaction holds it
+    /// for writing while it removes segments, so no I/O refers to them anymore.
+    segment_files: RwLock<()>,
+    /// Serializes compactions
+    compaction: Mutex<()>,
+    /// Whether a compaction is started in the background
+    compacting: AtomicBool,
+}
+
+/// Appends blocks to large segment files instead of keeping a file per block,
+/// which saves inodes and space for blocks much smaller than the block size.
+/// Every disk keeps its segments and an append-only index log of the locations
+/// of its blocks, which is rewritten whenever segments with mostly deleted data
+/// are compacted. Disks and space of blocks are accounted by `DataNodeInfo`, as
+/// for block files.
+pub struct PackedBackend {
+    data_node_info: Arc<DataNodeInfo>,
+    /// Store of every disk in the order of `DiskSpace::disks`
+    stores: Vec<Arc<Store>>,
+}
+
+fn io_error(err: std::io::Error) -> DataNodeError {
+    DataNodeError::UpdateBlockError(err.to_string())
+}
+
+fn read_error(err: std::io::Error) -> DataNodeError {
+    DataNodeError::ReadBlockError(err.to_string())
+}
+
+fn not_found((block_id, part): (Uuid, usize)) -> DataNodeError {
+    DataNodeError::BlockNotFound(format!("{}_{} not found.", block_id.as_u128(), part))
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl Store {
+    /// Opens the store in `directory`, replaying its index log.
+    async fn open(directory: PathBuf, segment_size: u64) -> std::io::Result<Self> {
+        tokio::fs::create_dir_all(&directory).await?;
+
+        let mut state = State::default();
+        if let Ok(log) = tokio::fs::read(directory.join(INDEX_FILE)).await {
+            // A record cut by a crash is ignored
+            for record in log.chunks_exact(RECORD_SIZE).filter_map(Record::decode) {
+                state.apply(record);
+            }
+        }
+
+        let mut dir = tokio::fs::read_dir(&directory).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name
+                .strip_prefix(SEGMENT_PREFIX)
+                .and_then(|id| id.parse::<u32>().ok())
+            else {
+                continue;
+            };
+
+            state.segments.insert(
+                id,
+                Segment {
+                    size: entry.metadata().await?.len(),
+                    live: 0,
+                },
+            );
+        }
+        for extent in state.blocks.values().flat_map(|block| block.extents.iter()) {
+            state.segments.entry(extent.segment).or_default().live += extent.len as u64;
+        }
+
+        state.active = state
+            .segments
+            .keys()
+            .next_back()
+            .copied()
+            .unwrap_or_default();
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(directory.join(INDEX_FILE))
+            .await?;
+
+        Ok(Self {
+            directory,
+            segment_size,
+            state: Mutex::new(state),
+            index: Mutex::new(index),
+            segment_files: RwLock::new(()),
+            compaction: Mutex::new(()),
+            compacting: AtomicBool::new(false),
+        })
+    }
+
+    fn segment_path(&self, segment: u32) -> PathBuf {
+        self.directory
+            .join(format!("{}{}", SEGMENT_PREFIX, segment))
+    }
+
+    /// Appends the record to the index log, syncs it and applies it to the state.
+    async fn log(&self, record: Record) -> Result<(), DataNodeError> {
+        let mut index = self.index.lock().await;
+        index.write_all(&record.encode()).await.map_err(io_error)?;
+        index.sync_data().await.map_err(io_error)?;
+        self.state.lock().await.apply(record);
+
+        Ok(())
+    }
+
+    /// Reserves `len` bytes at the end of the active segment, starting a new one
+    /// when it is full.
+    async fn reserve(&self, len: usize) -> Extent {
+        let mut state = self.state.lock().await;
+        let size = state
+            .segments
+            .get(&state.active)
+            .map(|segment| segment.size)
+            .unwrap_or_default();
+        if size > 0 && size + len as u64 > self.segment_size {
+            state.active += 1;
+        }
+
+        let active = state.active;
+        let segment = state.segments.entry(active).or_default();
+        let offset = segment.size;
+        segment.size += len as u64;
+        segment.live += len as u64;
+
+        Extent {
+            block_offset: 0,
+            segment: active,
+            offset,
+            len,
+        }
+    }
+
+    /// Writes `data` to a newly reserved extent and syncs it, so the record which
+    /// refers to it is never logged before the data. The caller holds `segment_files`.
+    async fn append(&self, data: &[u8]) -> Result<Extent, DataNodeError> {
+        let extent = self.reserve(data.len()).await;
+
+        let written = async {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(self.segment_path(extent.segment))
+                .await?;
+            file.seek(SeekFrom::Start(extent.offset)).await?;
+            file.write_all(data).await?;
+            file.sync_data().await
+        };
+        if let Err(err) = written.await {
+            self.state.lock().await.drop_live(&[extent]);
+            return Err(io_error(err));
+        }
+
+        Ok(extent)
+    }
+
+    /// Reads `range` of the block from the extents which overlap it. The caller
+    /// holds `segment_files`.
+    async fn read_extents(
+        &self,
+        extents: &[Extent],
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, DataNodeError> {
+        let mut data = vec![0; range.len()];
+        let mut files = HashMap::new();
+
+        for extent in extents {
+            let overlap = extent.overlap(&range);
+            if overlap.is_empty() {
+                continue;
+            }
+
+            let file = match files.entry(extent.segment) {
+                Entry::Occupied(file) => file.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    File::open(self.segment_path(extent.segment))
+                        .await
+                        .map_err(read_error)?,
+                ),
+            };
+            file.seek(SeekFrom::Start(
+                extent.offset + (overlap.start - extent.block_offset) as u64,
+            ))
+            .await
+            .map_err(read_error)?;
+            file.read_exact(&mut data[overlap.start - range.start..overlap.end - range.start])
+                .await
+                .map_err(read_error)?;
+        }
+
+        Ok(data)
+    }
+
+    async fn create(&self, block: (Uuid, usize)) -> Result<(), DataNodeError> {
+        self.log(Record::Create {
+            block,
+            modified: unix_time(),
+        })
+        .await
+    }
+
+    async fn read(
+        &self,
+        block: (Uuid, usize),
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, DataNodeError> {
+        let _segment_files = self.segment_files.read().await;
+        let (size, extents) = {
+            let state = self.state.lock().await;
+            let packed = state.blocks.get(&block).ok_or_else(|| not_found(block))?;
+            let extents = packed
+                .extents
+                .iter()
+                .filter(|extent| !extent.overlap(&range).is_empty())
+                .copied()
+                .collect::<Vec<_>>();
+            (packed.size(), extents)
+        };
+
+        if range.start > range.end || range.end > size {
+            return Err(DataNodeError::ReadBlockError(format!(
+                "{:?} is out of block with length {}",
+                range, size
+            )));
+        }
+
+        self.read_extents(&extents, range).await
+    }
+
+    async fn write(
+        &self,
+        block: (Uuid, usize),
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), DataNodeError> {
+        let _segment_files = self.segment_files.read().await;
+        if !self.state.lock().await.blocks.contains_key(&block) {
+            return Err(not_found(block));
+        }
+
+        let extent = self.append(data).await?;
+        let logged = self
+            .log(Record::Write {
+                block,
+                extent: Extent {
+                    block_offset: offset,
+                    ..extent
+                },
+                modified: unix_time(),
+            })
+            .await;
+        if logged.is_err() {
+            self.state.lock().await.drop_live(&[extent]);
+        }
+
+        logged
+    }
+
+    async fn delete(self: &Arc<Self>, block: (Uuid, usize)) -> Result<(), DataNodeError> {
+        if !self.state.lock().await.blocks.contains_key(&block) {
+            return Err(DataNodeError::DeleteBlockError(block.0.to_string()));
+        }
+        self.log(Record::Delete { block }).await?;
+
+        if !self.state.lock().await.sparse_segments().is_empty()
+            && !self.compacting.swap(true, Ordering::AcqRel)
+        {
+            let store = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = store.compact().await {
+                    tracing::warn!("Can not compact {:?}: {}", store.directory, err);
+                }
+                store.compacting.store(false, Ordering::Release);
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn size(&self, block: (Uuid, usize)) -> Result<usize, DataNodeError> {
+        self.state
+            .lock()
+            .await
+            .blocks
+            .get(&block)
+            .map(|block| block.size())
+            .ok_or_else(|| not_found(block))
+    }
+
+    /// Moves live blocks out of sparse segments, rewrites the index log with the
+    /// current locations only and deletes the segments nothing refers to anymore.
+    /// Blocks are read, written and deleted meanwhile.
+    async fn compact(&self) -> Result<(), DataNodeError> {
+        let _compaction = self.compaction.lock().await;
+        let (sparse, moved) = {
+            let state = self.state.lock().await;
+            let sparse = state.sparse_segments();
+            let moved = state
+                .blocks
+                .iter()
+                .filter(|(_, block)| {
+                    block
+                        .extents
+                        .iter()
+                        .any(|extent| sparse.contains(&extent.segment))
+                })
+                .map(|(&block, packed)| (block, packed.extents.clone()))
+                .collect::<Vec<_>>();
+            (sparse, moved)
+        };
+
+        if sparse.is_empty() {
+            return Ok(());
+        }
+
+        for (block, extents) in moved {
+            self.relocate(block, extents).await?;
+        }
+        self.rewrite_index().await?;
+
+        let _segment_files = self.segment_files.write().await;
+        let removed = {
+            let mut state = self.state.lock().await;
+            // Writes which reserved space before the segment became sparse may
+            // still refer to it, it is compacted next time
+            let referenced = state
+                .blocks
+                .values()
+                .flat_map(|block| block.extents.iter().map(|extent| extent.segment))
+                .collect::<HashSet<_>>();
+            sparse
+                .into_iter()
+                .filter(|id| !referenced.contains(id) && state.segments.remove(id).is_some())
+                .collect::<Vec<_>>()
+        };
+        for id in removed {
+            if let Err(err) = tokio::fs::remove_file(self.segment_path(id)).await {
+                tracing::warn!("Can not remove segment {}: {}", id, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the data of the block stored in `extents` to one extent of the
+    /// active segment, unless the block was written or deleted meanwhile.
+    async fn relocate(
+        &self,
+        block: (Uuid, usize),
+        extents: Vec<Extent>,
+    ) -> Result<(), DataNodeError> {
+        let _segment_files = self.segment_files.read().await;
+        let data = self.read_extents(&extents, 0..size(&extents)).await?;
+        let extent = self.append(&data).await?;
+
+        let mut state = self.state.lock().await;
+        match state.blocks.get_mut(&block) {
+            Some(packed) if packed.extents == extents => {
+                packed.extents = vec![extent];
+                state.drop_live(&extents);
+            }
+            _ => state.drop_live(&[extent]),
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the index log with the records of the current locations.
+    async fn rewrite_index(&self) -> Result<(), DataNodeError> {
+        let mut index = self.index.lock().await;
+        let log = {
+            let state = self.state.lock().await;
+            let mut log = Vec::with_capacity(state.blocks.len() * RECORD_SIZE * 2);
+            for (&block, packed) in &state.blocks {
+                log.extend(
+                    Record::Create {
+                        block,
+                        modified: packed.modified,
+                    }
+                    .encode(),
+                );
+                for &extent in &packed.extents {
+                    log.extend(
+                        Record::Write {
+                            block,
+                            extent,
+                            modified: packed.modified,
+                        }
+                        .encode(),
+                    );
+                }
+            }
+            log
+        };
+
+        let temporary = self.directory.join(format!("{}.tmp", INDEX_FILE));
+        let replaced = async {
+            let mut file = File::create(&temporary).await?;
+            file.write_all(&log).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&temporary, self.directory.join(INDEX_FILE)).await?;
+            // Makes the rename durable
+            File::open(&self.directory).await?.sync_all().await
+        };
+        replaced.await.map_err(io_error)?;
+        *index = OpenOptions::new()
+            .append(true)
+            .open(self.directory.join(INDEX_FILE))
+            .await
+            .map_err(io_error)?;
+
+        Ok(())
+    }
+}
+
+impl PackedBackend {
+    /// Opens the store in the working directory of every disk and accounts the
+    /// blocks found in them.
+    pub async fn open(
+        data_node_info: Arc<DataNodeInfo>,
+        segment_size: u64,
+    ) -> std::io::Result<Self> {
+        let space = &data_node_info.space;
+        let mut stores = Vec::with_capacity(space.disks.len());
+
+        for (index, disk) in space.disks.iter().enumerate() {
+            let store = Store::open(
+                disk.mount.join(&data_node_info.working_directory),
+                segment_size,
+            )
+            .await?;
+
+            let state = store.state.lock().await;
+            for (&(block_id, part), block) in &state.blocks {
+                let entry = BlockEntry {
+                    disk: index,
+                    size: block.size(),
+                    modified: block.modified,
+                    checksum: None,
+                };
+                data_node_info
+                    .block_index
+                    .insert(block_id, part, entry)
+                    .await;
+            }
+            let used_space = state.blocks.len() as u64 * data_node_info.block_size as u64;
+            drop(state);
+            space.add_used(index, used_space).await;
+
+            stores.push(Arc::new(store));
+        }
+
+        Ok(Self {
+            data_node_info,
+            stores,
+        })
+    }
+
+    /// Store of the disk the block is placed on.
+    async fn store(&self, block_id: Uuid, part: usize) -> Result<&Arc<Store>, DataNodeError> {
+        match self.data_node_info.block_index.get(block_id, part).await {
+            Some(entry) => Ok(&self.stores[entry.disk]),
+            None => Err(not_found((block_id, part))),
+        }
+    }
+}
+
+#[async_trait]
+impl BlockBackend for PackedBackend {
+    async fn create(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
+        if self.store(block_id, part).await.is_ok() {
+            return Ok(());
+        }
+
+        let disk = self.data_node_info.reserve_block(block_id, part).await?;
+        if let Err(err) = self.stores[disk].create((block_id, part)).await {
+            self.data_node_info.release_block(block_id, part).await;
+            return Err(DataNodeError::CreateBlockError(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn read_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, DataNodeError> {
+        self.store(block_id, part)
+            .await?
+            .read((block_id, part), range)
+            .await
+    }
+
+    async fn write_range(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), DataNodeError> {
+        self.store(block_id, part)
+            .await?
+            .write((block_id, part), offset, data)
+            .await
+    }
+
+    async fn delete(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
+        let store = self
+            .store(block_id, part)
+            .await
+            .map_err(|_| DataNodeError::DeleteBlockError(block_id.to_string()))?;
+        store.delete((block_id, part)).await?;
+        self.data_node_info.release_block(block_id, part).await;
+
+        Ok(())
+    }
+
+    async fn size(&self, block_id: Uuid, part: usize) -> Result<usize, DataNodeError> {
+        self.store(block_id, part)
+            .await?
+            .size((block_id, part))
+            .await
+    }
+
+    async fn checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        let store = self.store(block_id, part).await?;
+        let size = store.size((block_id, part)).await?;
+
+        Ok(hash_algorithm.hash(&store.read((block_id, part), 0..size).await?))
+    }
+
+    async fn list(&self) -> Result<Vec<StoredBlock>, DataNodeError> {
+        let mut blocks = vec![];
+        for store in &self.stores {
+            let state = store.state.lock().await;
+            blocks.extend(
+                state
+                    .blocks
+                    .iter()
+                    .map(|(&(block_id, part), block)| StoredBlock {
+                        block_id,
+                        part,
+                        modified: block.modified,
+                    }),
+            );
+        }
+
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_node_info::DiskSpace;
+    use crate::disk_stats::DiskStats;
+
+    fn data_node_info(mount: PathBuf, block_size: usize) -> Arc<DataNodeInfo> {
+        Arc::new(DataNodeInfo {
+            port: 40000,
+            self_address: "[::1]".to_string(),
+            working_directory: PathBuf::from("packed"),
+            block_size,
+            io_buffer: block_size,
+            space: Arc::new(DiskSpace {
+                total_space: 1 << 20,
+                disks: vec![DiskStats {
+                    available_space: 1 << 20,
+                    used_space: RwLock::new(0),
+                    mount,
+                }],
+                ..Default::default()
+            }),
+            block_index: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_packed_compaction_and_reopen() {
+        let mount = std::env::temp_dir().join(format!("packed_{}", Uuid::new_v4()));
+        let directory = mount.join("packed");
+        let ids = (0..8).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let expected = |i: usize| [[i as u8; 8].as_slice(), &[0xff; 16]].concat();
+
+        {
+            // Every segment of 96 bytes holds three blocks written in two extents
+            let info = data_node_info(mount.clone(), 32);
+            let backend = PackedBackend::open(info.clone(), 96).await.unwrap();
+            for (i, &id) in ids.iter().enumerate() {
+                backend.create(id, 0).await.unwrap();
+                backend.write_range(id, 0, 0, &[i as u8; 16]).await.unwrap();
+                backend.write_range(id, 0, 8, &[0xff; 16]).await.unwrap();
+            }
+
+            for i in [0, 1, 3, 4] {
+                backend.delete(ids[i], 0).await.unwrap();
+            }
+            // Waits for the compaction started in the background
+            backend.stores[0].compact().await.unwrap();
+            assert_eq!(4, info.get_stats().await.block_count);
+        }
+
+        let segments = std::fs::read_dir(&directory)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(SEGMENT_PREFIX)
+            })
+            .count();
+        assert_eq!(2, segments);
+
+        let info = data_node_info(mount.clone(), 32);
+        let backend = PackedBackend::open(info.clone(), 96).await.unwrap();
+        assert_eq!(4, backend.list().await.unwrap().len());
+        assert_eq!(4 * 32, info.get_stats().await.used_space);
+        for i in [2, 5, 6, 7] {
+            assert_eq!(
+                expected(i),
+                backend.read_range(ids[i], 0, 0..24).await.unwrap()
+            );
+            assert_eq!(
+                expected(i)[4..12],
+                backend.read_range(ids[i], 0, 4..12).await.unwrap()
+            );
+        }
+        assert!(backend.size(ids[0], 0).await.is_err());
+
+        let _ = std::fs::remove_dir_all(mount);
+    }
+}
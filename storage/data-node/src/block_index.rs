@@ -0,0 +1,420 @@
+use shared::hash::{Checksum, HashAlgorithm};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// op, block id, part, disk, size, modified, hash algorithm, checksum length
+const HEADER_SIZE: usize = 1 + 16 + 8 + 4 + 8 + 8 + 1 + 1;
+/// Hash algorithm byte of entries without a checksum
+const NO_CHECKSUM: u8 = u8::MAX;
+/// Log records after which the snapshot is rewritten
+const COMPACTION_RECORDS: usize = 4096;
+
+/// Location and contents of a block file.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BlockEntry {
+    /// Index in `DataNodeInfo::disks`
+    pub(crate) disk: usize,
+    pub(crate) size: usize,
+    /// Unix time in seconds of the last modification
+    pub(crate) modified: u64,
+    /// Checksum of the current data, dropped on every write
+    pub(crate) checksum: Option<(HashAlgorithm, Checksum)>,
+}
+
+impl BlockEntry {
+    pub(crate) fn new(disk: usize) -> Self {
+        Self {
+            disk,
+            size: 0,
+            modified: unix_time(),
+            checksum: None,
+        }
+    }
+}
+
+/// Entry of the append-only index log. The snapshot holds only `Put` records.
+enum Record {
+    Put {
+        block: (Uuid, usize),
+        entry: BlockEntry,
+    },
+    Remove {
+        block: (Uuid, usize),
+    },
+}
+
+impl Record {
+    fn encode(&self) -> Vec<u8> {
+        let (op, (block_id, part), entry) = match self {
+            Record::Put { block, entry } => (1u8, *block, Some(entry)),
+            Record::Remove { block } => (2u8, *block, None),
+        };
+        let (disk, size, modified, checksum) = entry.map_or((0, 0, 0, None), |entry| {
+            (
+                entry.disk,
+                entry.size,
+                entry.modified,
+                entry.checksum.as_ref(),
+            )
+        });
+        let (hash_algorithm, checksum) = checksum.map_or((NO_CHECKSUM, [].as_slice()), |c| {
+            (i32::from(c.0) as u8, c.1.as_slice())
+        });
+
+        let mut buffer = Vec::with_capacity(HEADER_SIZE + checksum.len());
+        buffer.push(op);
+        buffer.extend_from_slice(block_id.as_bytes());
+        buffer.extend_from_slice(&(part as u64).to_le_bytes());
+        buffer.extend_from_slice(&(disk as u32).to_le_bytes());
+        buffer.extend_from_slice(&(size as u64).to_le_bytes());
+        buffer.extend_from_slice(&modified.to_le_bytes());
+        buffer.push(hash_algorithm);
+        buffer.push(checksum.len() as u8);
+        buffer.extend_from_slice(checksum);
+
+        buffer
+    }
+
+    /// Decodes the record at the start of `buffer` and returns it with its length.
+    fn decode(buffer: &[u8]) -> Option<(Self, usize)> {
+        if buffer.len() < HEADER_SIZE {
+            return None;
+        }
+        let len = HEADER_SIZE + buffer[HEADER_SIZE - 1] as usize;
+        if buffer.len() < len {
+            return None;
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(buffer[at..at + 8].try_into().unwrap());
+
+        let block = (Uuid::from_slice(&buffer[1..17]).ok()?, u64_at(17) as usize);
+        let checksum = match buffer[HEADER_SIZE - 2] {
+            NO_CHECKSUM => None,
+            hash_algorithm => Some((
+                HashAlgorithm::try_from(hash_algorithm as i32).ok()?,
+                buffer[HEADER_SIZE..len].to_vec(),
+            )),
+        };
+
+        let record = match buffer[0] {
+            1 => Record::Put {
+                block,
+                entry: BlockEntry {
+                    disk: u32::from_le_bytes(buffer[25..29].try_into().unwrap()) as usize,
+                    size: u64_at(29) as usize,
+                    modified: u64_at(37),
+                    checksum,
+                },
+            },
+            2 => Record::Remove { block },
+            _ => return None,
+        };
+
+        Some((record, len))
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    blocks: HashMap<(Uuid, usize), BlockEntry>,
+    /// Missing when the index is kept in memory only
+    log: Option<File>,
+    /// Records appended to the log since the snapshot was written
+    log_records: usize,
+}
+
+/// Index of the block files of a data node, so blocks are found without probing
+/// every disk. Changes are appended to a log next to a snapshot, both rewritten
+/// on startup after the index is reconciled with the files found on disks.
+#[derive(Debug, Default)]
+pub(crate) struct BlockIndex {
+    /// Path of the snapshot, the log has the `.log` extension added
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl BlockIndex {
+    /// Loads the index at `path` and replaces it with `scanned` blocks. Checksums
+    /// are kept for blocks whose size and modification time have not changed.
+    pub(crate) async fn open<P: Into<PathBuf>>(
+        path: P,
+        scanned: HashMap<(Uuid, usize), BlockEntry>,
+    ) -> Self {
+        let path = path.into();
+        let mut stored = HashMap::new();
+        for file in [path.clone(), Self::log_path(&path)] {
+            if let Ok(buffer) = tokio::fs::read(&file).await {
+                Self::replay(&buffer, &mut stored);
+            }
+        }
+
+        let mut blocks = scanned;
+        for (block, entry) in blocks.iter_mut() {
+            if let Some(stored) = stored.remove(block) {
+                if stored.size == entry.size && stored.modified == entry.modified {
+                    entry.checksum = stored.checksum;
+                }
+            }
+        }
+
+        let index = Self {
+            path,
+            state: Mutex::new(State {
+                blocks,
+                log: None,
+                log_records: 0,
+            }),
+        };
+
+        let mut state = index.state.lock().await;
+        if let Err(err) = index.compact(&mut state).await {
+            tracing::warn!(
+                "Can't write the block index {}: {}. It will be rebuilt on restart.",
+                index.path.to_string_lossy(),
+                err
+            );
+        }
+        drop(state);
+
+        index
+    }
+
+    pub(crate) async fn get(&self, block_id: Uuid, part: usize) -> Option<BlockEntry> {
+        self.state
+            .lock()
+            .await
+            .blocks
+            .get(&(block_id, part))
+            .cloned()
+    }
+
+    pub(crate) async fn insert(&self, block_id: Uuid, part: usize, entry: BlockEntry) {
+        let mut state = self.state.lock().await;
+        state.blocks.insert((block_id, part), entry.clone());
+        self.append(
+            &mut state,
+            Record::Put {
+                block: (block_id, part),
+                entry,
+            },
+        )
+        .await;
+    }
+
+    pub(crate) async fn remove(&self, block_id: Uuid, part: usize) -> Option<BlockEntry> {
+        let mut state = self.state.lock().await;
+        let entry = state.blocks.remove(&(block_id, part))?;
+        self.append(
+            &mut state,
+            Record::Remove {
+                block: (block_id, part),
+            },
+        )
+        .await;
+
+        Some(entry)
+    }
+
+    /// Grows the block to `end` if needed and drops its checksum.
+    pub(crate) async fn record_write(&self, block_id: Uuid, part: usize, end: usize) {
+        self.update(block_id, part, |entry| {
+            entry.size = entry.size.max(end);
+            entry.modified = unix_time();
+            entry.checksum = None;
+        })
+        .await
+    }
+
+    pub(crate) async fn record_checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+        checksum: Checksum,
+    ) {
+        self.update(block_id, part, |entry| {
+            entry.checksum = Some((hash_algorithm, checksum))
+        })
+        .await
+    }
+
+    pub(crate) async fn blocks(&self) -> Vec<((Uuid, usize), BlockEntry)> {
+        self.state
+            .lock()
+            .await
+            .blocks
+            .iter()
+            .map(|(&block, entry)| (block, entry.clone()))
+            .collect()
+    }
+
+    pub(crate) async fn len(&self) -> usize {
+        self.state.lock().await.blocks.len()
+    }
+
+    async fn update<F: FnOnce(&mut BlockEntry)>(&self, block_id: Uuid, part: usize, f: F) {
+        let mut state = self.state.lock().await;
+        let Some(entry) = state.blocks.get_mut(&(block_id, part)) else {
+            return;
+        };
+        f(entry);
+
+        let entry = entry.clone();
+        self.append(
+            &mut state,
+            Record::Put {
+                block: (block_id, part),
+                entry,
+            },
+        )
+        .await;
+    }
+
+    /// Persists the change, a lost record only costs the checksum or a rescan on restart.
+    async fn append(&self, state: &mut State, record: Record) {
+        let Some(log) = state.log.as_mut() else {
+            return;
+        };
+
+        if let Err(err) = log.write_all(&record.encode()).await {
+            tracing::error!("Can't append to the block index: {}", err);
+            return;
+        }
+        state.log_records += 1;
+
+        if state.log_records > COMPACTION_RECORDS.max(state.blocks.len()) {
+            if let Err(err) = self.compact(state).await {
+                tracing::error!("Can't compact the block index: {}", err);
+            }
+        }
+    }
+
+    /// Writes all entries to a new snapshot and starts an empty log.
+    async fn compact(&self, state: &mut State) -> std::io::Result<()> {
+        state.log = None;
+
+        let mut buffer = vec![];
+        for (&block, entry) in &state.blocks {
+            buffer.extend(
+                Record::Put {
+                    block,
+                    entry: entry.clone(),
+                }
+                .encode(),
+            );
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, buffer).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        let log = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(Self::log_path(&self.path))
+            .await?;
+        state.log = Some(log);
+        state.log_records = 0;
+
+        Ok(())
+    }
+
+    fn replay(mut buffer: &[u8], blocks: &mut HashMap<(Uuid, usize), BlockEntry>) {
+        // A torn record at the end is dropped, the scan on startup covers it
+        while let Some((record, len)) = Record::decode(buffer) {
+            match record {
+                Record::Put { block, entry } => {
+                    blocks.insert(block, entry);
+                }
+                Record::Remove { block } => {
+                    blocks.remove(&block);
+                }
+            }
+            buffer = &buffer[len..];
+        }
+    }
+
+    fn log_path(path: &Path) -> PathBuf {
+        let mut log = path.as_os_str().to_owned();
+        log.push(".log");
+        log.into()
+    }
+
+    /// Reads the whole log, used to check what survives a restart.
+    #[cfg(test)]
+    async fn read_log(&self) -> Vec<u8> {
+        tokio::fs::read(Self::log_path(&self.path))
+            .await
+            .unwrap_or_default()
+    }
+}
+
+/// Builds block file name in `<uuid as u128>_<part>` format.
+pub(crate) fn block_name(block_id: Uuid, part: usize) -> String {
+    format!("{}_{}", block_id.as_u128(), part)
+}
+
+/// Parses block file name in `<uuid as u128>_<part>` format.
+pub(crate) fn parse_block_name(name: &str) -> Option<(Uuid, usize)> {
+    let (uuid, part) = name.split_once('_')?;
+
+    Some((Uuid::from_u128(uuid.parse().ok()?), part.parse().ok()?))
+}
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_block_index_reopen() {
+        let path = std::env::temp_dir().join(format!("block_index_{}", Uuid::new_v4()));
+        let (kept, changed, removed) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let index = BlockIndex::open(&path, HashMap::new()).await;
+        for id in [kept, changed, removed] {
+            index.insert(id, 0, BlockEntry::new(1)).await;
+            index.record_write(id, 0, 16).await;
+            index
+                .record_checksum(id, 0, HashAlgorithm::Crc32, vec![1, 2, 3, 4])
+                .await;
+        }
+        index.remove(removed, 0).await;
+        assert!(!index.read_log().await.is_empty());
+
+        // The scan finds the files of all blocks, one of them was written after the last record
+        let mut scanned = HashMap::new();
+        for id in [kept, changed] {
+            let mut entry = index.get(id, 0).await.unwrap();
+            entry.checksum = None;
+            scanned.insert((id, 0), entry);
+        }
+        scanned.get_mut(&(changed, 0)).unwrap().size = 32;
+        drop(index);
+
+        let index = BlockIndex::open(&path, scanned).await;
+        assert!(index.read_log().await.is_empty());
+        assert_eq!(index.len().await, 2);
+        assert_eq!(
+            index.get(kept, 0).await.unwrap().checksum,
+            Some((HashAlgorithm::Crc32, vec![1, 2, 3, 4]))
+        );
+        assert_eq!(index.get(changed, 0).await.unwrap().checksum, None);
+        assert_eq!(index.get(changed, 0).await.unwrap().disk, 1);
+        assert!(index.get(removed, 0).await.is_none());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(BlockIndex::log_path(&path));
+    }
+}
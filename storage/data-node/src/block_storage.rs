@@ -1,27 +1,69 @@
-use crate::data_node_info::DataNodeInfo;
-use futures::TryFutureExt;
+use crate::block_backend::file_system::FileSystemBackend;
+use crate::block_backend::memory::MemoryBackend;
+use crate::block_backend::packed::PackedBackend;
+use crate::block_backend::{BlockBackend, StoredBlock};
+use crate::config::{Config, StorageEngine};
+use crate::data_node_info::{DataNodeInfo, DiskSpace};
 use shared::data_node_error::DataNodeError;
-use std::io::SeekFrom;
+use shared::hash::{Checksum, HashAlgorithm};
 use std::ops::Range;
-use std::path::{Path, PathBuf};
-use tokio::fs::OpenOptions;
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter};
+use std::sync::Arc;
 use uuid::Uuid;
 
 pub struct BlockStorage {
-    data_node_info: DataNodeInfo,
+    data_node_info: Arc<DataNodeInfo>,
+    backend: Box<dyn BlockBackend>,
 }
 
 impl BlockStorage {
     pub async fn new(data_node_info: DataNodeInfo) -> std::io::Result<Self> {
-        for disk in &data_node_info.disks {
-            let path = disk.mount.join(&data_node_info.working_directory);
-            if !path.exists() {
-                tokio::fs::create_dir(path).await?;
-            }
+        let data_node_info = Arc::new(data_node_info);
+        let backend = FileSystemBackend::new(data_node_info.clone()).await?;
+
+        Ok(Self {
+            data_node_info,
+            backend: Box::new(backend),
+        })
+    }
+
+    pub fn with_backend(data_node_info: DataNodeInfo, backend: Box<dyn BlockBackend>) -> Self {
+        Self {
+            data_node_info: Arc::new(data_node_info),
+            backend,
         }
+    }
 
-        Ok(Self { data_node_info })
+    /// Opens the pool of `block_size` with the configured storage engine on the
+    /// disks shared by all pools.
+    pub async fn open(
+        mut config: Config,
+        block_size: usize,
+        space: Arc<DiskSpace>,
+    ) -> std::io::Result<Self> {
+        config.block_size = block_size;
+        let storage_engine = config.storage_engine;
+        let segment_size = config.segment_size;
+        let data_node_info = DataNodeInfo::new(config, space).await;
+
+        match storage_engine {
+            StorageEngine::Files => Self::new(data_node_info).await,
+            StorageEngine::Packed => {
+                let data_node_info = Arc::new(data_node_info);
+                let backend = PackedBackend::open(data_node_info.clone(), segment_size).await?;
+
+                Ok(Self {
+                    data_node_info,
+                    backend: Box::new(backend),
+                })
+            }
+            StorageEngine::Memory => {
+                tracing::warn!("Blocks are kept in memory and will be lost on restart");
+                Ok(Self::with_backend(
+                    data_node_info,
+                    Box::new(MemoryBackend::new()),
+                ))
+            }
+        }
     }
 
     pub fn get_endpoint(&self) -> String {
@@ -33,51 +75,19 @@ impl BlockStorage {
         part: usize,
         uuid: Uuid,
     ) -> Result<(usize, Uuid), DataNodeError> {
-        let disk = self.data_node_info.get_disk_for_new_block().await?;
-
-        let _ = OpenOptions::new()
-            .write(true)
-            .read(false)
-            .create(true)
-            .open(
-                disk.mount
-                    .join(&self.data_node_info.working_directory)
-                    .join(format!("{}_{}", uuid.as_u128(), part)),
-            )
-            .await
-            .map_err(|err| DataNodeError::CreateBlockError(err.to_string()))?;
+        self.backend.create(uuid, part).await?;
 
         Ok((part, uuid))
     }
 
     /// Лучше возврощать поток чтения, чтобы напрямую передавать байты
-    pub async fn read_block<P: AsRef<Path>>(
+    pub async fn read_block(
         &self,
-        path: P,
+        block_id: Uuid,
+        part: usize,
         bytes: Range<usize>,
     ) -> Result<Vec<u8>, DataNodeError> {
-        let file = OpenOptions::new()
-            .write(false)
-            .read(true)
-            .open(path)
-            .await
-            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?;
-        let mut reader = BufReader::new(file);
-        let mut buffer = vec![0; bytes.len()];
-        reader
-            .seek(SeekFrom::Start(bytes.start as u64))
-            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))
-            .await?;
-        reader
-            .read_exact(&mut buffer)
-            .await
-            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?;
-        reader
-            .flush()
-            .await
-            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?;
-
-        Ok(buffer)
+        self.backend.read_range(block_id, part, bytes).await
     }
 
     pub async fn update_block(
@@ -87,7 +97,7 @@ impl BlockStorage {
         bytes: Range<usize>,
         data: &[u8],
     ) -> Result<(), DataNodeError> {
-        let (path, size) = self.get_block_info(block_id, part).await?;
+        let size = self.get_block_size(block_id, part).await?;
 
         if data.len() > self.data_node_info.block_size {
             return Err(DataNodeError::BlockOverflow(
@@ -107,115 +117,111 @@ impl BlockStorage {
             ));
         }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .read(false)
-            .open(&path)
-            .await
-            .map_err(|_| DataNodeError::UpdateBlockError(block_id.to_string()))?;
-
-        let mut writer = BufWriter::new(file);
-
-        writer
-            .seek(SeekFrom::Start(bytes.start as u64))
-            .await
-            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
-        writer
-            .write_all(data)
-            .await
-            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
-        writer
-            .flush()
-            .await
-            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+        self.backend
+            .write_range(block_id, part, bytes.start, data)
+            .await?;
+        self.data_node_info
+            .block_index
+            .record_write(block_id, part, bytes.start + data.len())
+            .await;
 
         Ok(())
     }
 
     pub async fn delete_block(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
-        let path = self
-            .data_node_info
-            .found_block(format!("{}_{}", block_id.as_u128(), part))
-            .await?;
-        tokio::fs::remove_file(path)
-            .await
-            .map_err(|_| DataNodeError::DeleteBlockError(block_id.to_string()))
+        self.backend.delete(block_id, part).await
     }
 
-    pub async fn get_block_info(
+    pub async fn get_block_size(
         &self,
         block_id: Uuid,
         part: usize,
-    ) -> Result<(PathBuf, usize), DataNodeError> {
-        let path = self
-            .data_node_info
-            .found_block(format!("{}_{}", block_id.as_u128(), part))
-            .await?;
-        let buffer_len = path
-            .metadata()
-            .map_err(|err| DataNodeError::ReadBlockError(err.to_string()))?
-            .len() as usize;
+    ) -> Result<usize, DataNodeError> {
+        match self.data_node_info.block_index.get(block_id, part).await {
+            Some(entry) => Ok(entry.size),
+            None => self.backend.size(block_id, part).await,
+        }
+    }
 
-        Ok((path, buffer_len))
+    /// Lists every stored block.
+    pub async fn list_blocks(&self) -> Result<Vec<StoredBlock>, DataNodeError> {
+        self.backend.list().await
     }
 
     pub fn get_data_node_info(&self) -> &DataNodeInfo {
         &self.data_node_info
     }
 
-    pub async fn get_checksum(&self, block_id: Uuid, part: usize) -> Result<u32, DataNodeError> {
-        let (path, _) = self.get_block_info(block_id, part).await?;
-
-        let file = OpenOptions::new()
-            .write(false)
-            .read(true)
-            .open(&path)
-            .await
-            .map_err(|_| DataNodeError::UpdateBlockError(block_id.to_string()))?;
+    /// Checksum of the block, taken from the block index if the block was not
+    /// written since it was computed.
+    pub async fn get_checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        let indexed = self.data_node_info.block_index.get(block_id, part).await;
+        if let Some((algorithm, checksum)) = indexed.and_then(|entry| entry.checksum) {
+            if algorithm == hash_algorithm {
+                return Ok(checksum);
+            }
+        }
 
-        let mut reader = BufReader::new(file);
-        let _ = reader
-            .seek(SeekFrom::Start(0))
-            .await
-            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+        self.verify_checksum(block_id, part, hash_algorithm).await
+    }
 
-        let mut buffer = vec![];
-        let _ = reader
-            .read_to_end(&mut buffer)
-            .await
-            .map_err(|err| DataNodeError::UpdateBlockError(err.to_string()))?;
+    /// Reads the whole block to compute its checksum.
+    pub async fn verify_checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        let checksum = self
+            .backend
+            .checksum(block_id, part, hash_algorithm)
+            .await?;
+        self.data_node_info
+            .block_index
+            .record_checksum(block_id, part, hash_algorithm, checksum.clone())
+            .await;
 
-        Ok(crc32fast::hash(&buffer))
+        Ok(checksum)
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
-    use crate::config::Config;
+    use std::path::PathBuf;
+
+    /// Pool which keeps blocks in memory and does not touch the disk.
+    pub(crate) fn memory_pool(block_size: usize, io_buffer: usize) -> BlockStorage {
+        let data_node_info = DataNodeInfo {
+            port: 40000,
+            self_address: "[::1]".to_string(),
+            working_directory: PathBuf::new(),
+            block_size,
+            io_buffer,
+            space: Default::default(),
+            block_index: Default::default(),
+        };
+
+        BlockStorage::with_backend(data_node_info, Box::new(MemoryBackend::new()))
+    }
 
     #[tokio::test]
     async fn test_block_storage_crud() {
         let message = b"Hello, Pavel";
-
-        let data_node_info = DataNodeInfo::new(Config {
-            main_server_address: "http://[::1]:8000".to_string(),
-            self_address: "http://[::1]".to_string(),
-            port: 40000,
-            block_size: 32,
-            max_small_file_size: 16,
-            disk_space: None,
-            working_directory: "test_dir".to_string(),
-            read_buffer: 8,
-        })
-            .await;
-        let buffer_size = data_node_info.io_buffer;
-
-        let data_node = BlockStorage::new(data_node_info).await.unwrap();
+        let buffer_size = 8;
+        let data_node = memory_pool(32, buffer_size);
         let (part, uuid) = data_node.create_block(0, Uuid::new_v4()).await.unwrap();
 
-        data_node.update_block(uuid, part, 0..message.len(), message).await.unwrap();
-        let (path, len) = data_node.get_block_info(uuid, part).await.unwrap();
+        data_node
+            .update_block(uuid, part, 0..message.len(), message)
+            .await
+            .unwrap();
+        let len = data_node.get_block_size(uuid, part).await.unwrap();
         let chunk_count = len / buffer_size;
         let last_chunk = len - chunk_count * buffer_size;
 
@@ -229,7 +235,7 @@ mod tests {
             } else {
                 (i * buffer_size)..((i + 1) * buffer_size)
             };
-            let read = data_node.read_block(&path, bytes).await.unwrap();
+            let read = data_node.read_block(uuid, part, bytes).await.unwrap();
             read_message = [read_message, read].concat();
         }
 
@@ -245,24 +251,15 @@ mod tests {
             .collect::<Vec<_>>();
         assert_eq!(message.len(), 13 * 4096);
 
-        let data_node_info = DataNodeInfo::new(Config {
-            main_server_address: "http://[::1]:8000".to_string(),
-            self_address: "http://[::1]".to_string(),
-            port: 40000,
-            block_size: 65536,
-            max_small_file_size: 65536,
-            disk_space: None,
-            working_directory: "test_dir".to_string(),
-            read_buffer: 1000,
-        })
-            .await;
-        let buffer_size = data_node_info.io_buffer;
-
-        let data_node = BlockStorage::new(data_node_info).await.unwrap();
+        let buffer_size = 1000;
+        let data_node = memory_pool(65536, buffer_size);
         let (part, uuid) = data_node.create_block(0, Uuid::new_v4()).await.unwrap();
 
-        data_node.update_block(uuid, part, 0..message.len(), &message).await.unwrap();
-        let (path, len) = data_node.get_block_info(uuid, part).await.unwrap();
+        data_node
+            .update_block(uuid, part, 0..message.len(), &message)
+            .await
+            .unwrap();
+        let len = data_node.get_block_size(uuid, part).await.unwrap();
         let chunk_count = len / buffer_size;
         let last_chunk = len - chunk_count * buffer_size;
         let mut futures = vec![];
@@ -278,7 +275,7 @@ mod tests {
                     } else {
                         (i * buffer_size)..((i + 1) * buffer_size)
                     };
-                    let read = data_node.read_block(&path, bytes).await.unwrap();
+                    let read = data_node.read_block(uuid, part, bytes).await.unwrap();
                     read_message = [read_message, read].concat();
                 }
                 read_message
@@ -292,13 +289,3 @@ mod tests {
         data_node.delete_block(uuid, part).await.unwrap();
     }
 }
-
-#[cfg(any(test, bench))]
-impl Drop for BlockStorage {
-    fn drop(&mut self) {
-        for disk in &self.data_node_info.disks {
-            let _ =
-                std::fs::remove_dir_all(disk.mount.join(&self.data_node_info.working_directory));
-        }
-    }
-}
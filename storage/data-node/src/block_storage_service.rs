@@ -1,30 +1,84 @@
+use crate::block_backend::StoredBlock;
 use crate::block_storage::BlockStorage;
-use crate::data_node_info::DataNodeInfo;
+use crate::data_node_info::{DataNodeStats, DiskUsage};
 use shared::data_node_error::DataNodeError;
+use shared::hash::{Checksum, HashAlgorithm};
 use std::ops::Range;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
+/// Serves a pool of blocks for every block size, each pool keeps its blocks in
+/// its own working directory.
 pub struct BlockStorageService {
-    block_storage: BlockStorage,
+    /// The default pool goes first
+    pools: Vec<BlockStorage>,
 }
 
 impl BlockStorageService {
-    pub async fn new(data_node_info: DataNodeInfo) -> std::io::Result<Self> {
-        let block_storage = BlockStorage::new(data_node_info).await?;
+    pub fn with_pools(pools: Vec<BlockStorage>) -> Self {
+        assert!(!pools.is_empty(), "Data node needs at least one pool");
 
-        Ok(Self { block_storage })
+        Self { pools }
     }
 
     pub fn get_endpoint(&self) -> String {
-        self.block_storage.get_endpoint()
+        self.pools[0].get_endpoint()
     }
 
-    pub async fn create_blocks(&self, count: usize) -> Result<Vec<(usize, Uuid)>, DataNodeError> {
-        let uuid = Uuid::new_v4();
+    /// Stats of all pools. Pools share the disks, so the space is counted once.
+    pub async fn get_stats(&self) -> DataNodeStats {
+        let mut stats = self.pools[0].get_data_node_info().get_stats().await;
+        for pool in &self.pools[1..] {
+            stats.block_count += pool.get_data_node_info().get_stats().await.block_count;
+        }
+
+        stats
+    }
+
+    /// Usage of every disk by all pools, with the total space of the data node.
+    pub async fn get_disk_usage(&self) -> (Vec<DiskUsage>, u64) {
+        let space = &self.pools[0].get_data_node_info().space;
+
+        (space.disk_usage().await, space.total_space)
+    }
+
+    /// Pool of `block_size`, zero stands for the default pool.
+    fn get_pool(&self, block_size: usize) -> Result<&BlockStorage, DataNodeError> {
+        if block_size == 0 {
+            return Ok(&self.pools[0]);
+        }
+
+        self.pools
+            .iter()
+            .find(|pool| pool.get_data_node_info().block_size == block_size)
+            .ok_or(DataNodeError::UnknownBlockSize(block_size))
+    }
+
+    /// Pool holding the block, the default one if no pool has it.
+    async fn find_pool(&self, block_id: Uuid, part: usize) -> &BlockStorage {
+        if let [pool] = self.pools.as_slice() {
+            return pool;
+        }
+
+        for pool in &self.pools {
+            if pool.get_block_size(block_id, part).await.is_ok() {
+                return pool;
+            }
+        }
+
+        &self.pools[0]
+    }
 
-        let tasks =
-            (0..count).map(|id| async move { self.block_storage.create_block(id, uuid).await });
+    pub async fn create_blocks(
+        &self,
+        uuid: Uuid,
+        parts: &[usize],
+        block_size: usize,
+    ) -> Result<Vec<(usize, Uuid)>, DataNodeError> {
+        let pool = self.get_pool(block_size)?;
+        let tasks = parts
+            .iter()
+            .map(|&part| async move { pool.create_block(part, uuid).await });
         let tasks = futures::future::join_all(tasks).await;
 
         if !tasks.iter().all(|block| block.is_ok()) {
@@ -42,7 +96,8 @@ impl BlockStorageService {
             }
 
             return Err(DataNodeError::CreateBlocksError(format!(
-                "Can not create {count} blocks"
+                "Can not create {} blocks",
+                parts.len()
             )));
         }
 
@@ -56,27 +111,25 @@ impl BlockStorageService {
         &self,
         block_id: Uuid,
         part: usize,
+        range: Option<Range<usize>>,
         tx: Sender<Result<Vec<u8>, DataNodeError>>,
     ) -> Result<(), DataNodeError> {
-        let (path, file_size) = self.block_storage.get_block_info(block_id, part).await?;
+        let pool = self.find_pool(block_id, part).await;
+        let file_size = pool.get_block_size(block_id, part).await?;
 
-        let buffer_size = self.block_storage.get_data_node_info().io_buffer;
+        let buffer_size = pool.get_data_node_info().io_buffer;
 
-        let chunk_count = file_size / buffer_size;
-        let last_chunk = file_size - chunk_count * buffer_size;
+        // Like a file read, the range is cut at the end of the block
+        let range = range.unwrap_or(0..file_size);
+        let end = range.end.min(file_size);
+        let mut offset = range.start.min(end);
 
-        for i in 0..(chunk_count + 1) {
-            let bytes = if i == chunk_count {
-                if last_chunk == 0 {
-                    break;
-                }
+        let mut i = 0;
+        while offset < end {
+            let bytes = offset..(offset + buffer_size).min(end);
+            offset = bytes.end;
 
-                (i * buffer_size)..(i * buffer_size + last_chunk)
-            } else {
-                (i * buffer_size)..((i + 1) * buffer_size)
-            };
-
-            let read = self.block_storage.read_block(&path, bytes).await;
+            let read = pool.read_block(block_id, part, bytes).await;
 
             match tx.send(read).await {
                 Ok(_) => {
@@ -87,6 +140,7 @@ impl BlockStorageService {
                     break;
                 }
             }
+            i += 1;
         }
 
         Ok(())
@@ -99,7 +153,8 @@ impl BlockStorageService {
         range: Range<usize>,
         data: &[u8],
     ) -> Result<(), DataNodeError> {
-        self.block_storage
+        self.find_pool(block_id, part)
+            .await
             .update_block(block_id, part, range, data)
             .await
     }
@@ -108,11 +163,111 @@ impl BlockStorageService {
         &self,
         block_id: Uuid,
         part: usize,
-    ) -> Result<u32, DataNodeError> {
-        self.block_storage.get_checksum(block_id, part).await
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        self.find_pool(block_id, part)
+            .await
+            .get_checksum(block_id, part, hash_algorithm)
+            .await
+    }
+
+    /// Recomputes the checksum from the stored data, bypassing the block index.
+    pub async fn verify_block_checksum(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        self.find_pool(block_id, part)
+            .await
+            .verify_checksum(block_id, part, hash_algorithm)
+            .await
     }
 
     pub async fn delete_block(&self, block_id: Uuid, part: usize) -> Result<(), DataNodeError> {
-        self.block_storage.delete_block(block_id, part).await
+        self.find_pool(block_id, part)
+            .await
+            .delete_block(block_id, part)
+            .await
+    }
+
+    pub async fn list_blocks(&self) -> Result<Vec<StoredBlock>, DataNodeError> {
+        let mut blocks = vec![];
+        for pool in &self.pools {
+            blocks.extend(pool.list_blocks().await?);
+        }
+
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_storage::tests::memory_pool;
+
+    #[tokio::test]
+    async fn test_read_block_range_in_memory() {
+        let message = b"Hello, Pavel";
+        let service = BlockStorageService::with_pools(vec![memory_pool(32, 4)]);
+        let uuid = Uuid::new_v4();
+
+        service.create_blocks(uuid, &[0], 0).await.unwrap();
+        service
+            .update_block(uuid, 0, 0..message.len(), message)
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        service.read_block(uuid, 0, Some(3..100), tx).await.unwrap();
+
+        let mut chunks = vec![];
+        while let Some(chunk) = rx.recv().await {
+            chunks.push(chunk.unwrap());
+        }
+
+        assert_eq!(3, chunks.len());
+        assert_eq!(&message[3..], chunks.concat().as_slice());
+        assert_eq!(
+            HashAlgorithm::Crc32.hash(message),
+            service
+                .get_block_checksum(uuid, 0, HashAlgorithm::Crc32)
+                .await
+                .unwrap()
+        );
+
+        service.delete_block(uuid, 0).await.unwrap();
+        assert!(service.list_blocks().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_block_size_pools() {
+        let service = BlockStorageService::with_pools(vec![memory_pool(32, 4), memory_pool(8, 4)]);
+        let (small, large) = (Uuid::new_v4(), Uuid::new_v4());
+
+        service.create_blocks(small, &[0], 8).await.unwrap();
+        service.create_blocks(large, &[0], 0).await.unwrap();
+        assert!(service.create_blocks(large, &[1], 16).await.is_err());
+
+        // Blocks are found in their pool and keep its size limit
+        assert!(service
+            .update_block(small, 0, 0..16, &[1; 16])
+            .await
+            .is_err());
+        service.update_block(small, 0, 0..8, &[1; 8]).await.unwrap();
+        service
+            .update_block(large, 0, 0..16, &[2; 16])
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        service.read_block(small, 0, None, tx).await.unwrap();
+        let mut chunks = vec![];
+        while let Some(chunk) = rx.recv().await {
+            chunks.push(chunk.unwrap());
+        }
+
+        assert_eq!(vec![1; 8], chunks.concat());
+        assert_eq!(2, service.list_blocks().await.unwrap().len());
     }
 }
@@ -1,6 +1,8 @@
 use clap::Parser;
-use serde::Deserialize;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
 use std::path::Path;
+use std::str::FromStr;
 use tokio::io::AsyncReadExt;
 
 #[derive(Clone, Deserialize, Parser)]
@@ -17,25 +19,144 @@ pub struct Config {
     /// Block size
     #[arg(short, long)]
     pub(crate) block_size: usize,
+    /// Block sizes of additional pools served next to the `block_size` one
+    #[arg(long)]
+    #[serde(default)]
+    pub(crate) pool_block_sizes: Vec<usize>,
     /// Maximum size of small file in KB
     #[arg(short, long)]
     pub(crate) max_small_file_size: usize,
     /// Volume of disk space to use in KB. If not set service will use all disk space
     #[arg(short, long)]
     pub(crate) disk_space: Option<u64>,
+    /// Disk space in KB kept free on every disk
+    #[arg(long, default_value_t = 102400)]
+    #[serde(default = "default_reserved_space")]
+    pub(crate) reserved_space: u64,
     /// Name of directory where will placed blocks
     #[arg(short, long)]
     pub(crate) working_directory: String,
     /// Buffer size for stream reading
     #[arg(short, long)]
     pub(crate) read_buffer: usize,
+    /// Interval between heartbeats to main server in seconds
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_heartbeat_interval", deserialize_with = "positive")]
+    pub(crate) heartbeat_interval: u64,
+    /// Count of blocks per second verified by scrubber. Zero disables scrubbing
+    #[arg(long, default_value_t = 10)]
+    #[serde(default = "default_scrub_rate")]
+    pub(crate) scrub_rate: u64,
+    /// Pause between scrubber passes in seconds
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_scrub_interval", deserialize_with = "positive")]
+    pub(crate) scrub_interval: u64,
+    /// Interval in seconds between block inventory reports to main server
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_inventory_interval", deserialize_with = "positive")]
+    pub(crate) inventory_interval: u64,
+    /// Where blocks are kept: "files" for a file per block, "packed" for blocks
+    /// appended to segment files or "memory" for blocks lost on restart
+    #[arg(long, default_value = "files")]
+    #[serde(default)]
+    pub(crate) storage_engine: StorageEngine,
+    /// Size of segment files of the packed storage engine in bytes
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    #[serde(default = "default_segment_size")]
+    pub(crate) segment_size: u64,
+    /// How a disk is chosen for a new block: "round-robin", "most-free" or "hash"
+    /// of the block name for deterministic placement
+    #[arg(long, default_value = "round-robin")]
+    #[serde(default)]
+    pub(crate) disk_policy: DiskPolicy,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageEngine {
+    #[default]
+    Files,
+    Packed,
+    Memory,
+}
+
+impl FromStr for StorageEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "files" => Ok(StorageEngine::Files),
+            "packed" => Ok(StorageEngine::Packed),
+            "memory" => Ok(StorageEngine::Memory),
+            s => Err(format!("Unknown storage engine {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiskPolicy {
+    #[default]
+    RoundRobin,
+    MostFree,
+    Hash,
+}
+
+impl FromStr for DiskPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(DiskPolicy::RoundRobin),
+            "most-free" => Ok(DiskPolicy::MostFree),
+            "hash" => Ok(DiskPolicy::Hash),
+            s => Err(format!("Unknown disk policy {}", s)),
+        }
+    }
+}
+
+fn default_reserved_space() -> u64 {
+    102400
+}
+
+fn default_heartbeat_interval() -> u64 {
+    5
+}
+
+fn default_scrub_rate() -> u64 {
+    10
+}
+
+fn default_scrub_interval() -> u64 {
+    3600
+}
+
+fn default_inventory_interval() -> u64 {
+    600
+}
+
+fn default_segment_size() -> u64 {
+    64 * 1024 * 1024
+}
+
+/// Rejects zero periods of timers, `tokio::time::interval` panics on them.
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let value = u64::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(D::Error::custom("must be greater than zero"));
+    }
+
+    Ok(value)
 }
 
 impl Config {
-    pub async fn try_from_file<P: AsRef<Path>>(path: P) -> Self {
-        Self::from_file(path)
-            .await
-            .unwrap_or_else(|_| Self::parse())
+    /// Reads the config file, the command line is parsed if there is no file. A file
+    /// with a wrong value fails with `InvalidData`.
+    pub async fn try_from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        match Self::from_file(path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::parse()),
+            config => config,
+        }
     }
 
     pub async fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
@@ -47,7 +168,8 @@ impl Config {
             .await?;
         let mut buffer = String::new();
         config_file.read_to_string(&mut buffer).await?;
-        let config: Config = toml::from_str(&buffer).unwrap();
+        let config: Config = toml::from_str(&buffer)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
 
         Ok(config)
     }
@@ -55,4 +177,16 @@ impl Config {
     pub fn get_main_server_addr(&self) -> &str {
         &self.main_server_address
     }
+
+    /// Block sizes of all pools, the default one goes first.
+    pub fn block_sizes(&self) -> Vec<usize> {
+        let mut block_sizes = vec![self.block_size];
+        for &block_size in &self.pool_block_sizes {
+            if !block_sizes.contains(&block_size) {
+                block_sizes.push(block_size);
+            }
+        }
+
+        block_sizes
+    }
 }
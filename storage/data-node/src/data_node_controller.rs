@@ -9,16 +9,19 @@ mod proto_data_node_api {
 use crate::block_storage_service::BlockStorageService;
 use crate::data_node_controller::proto_data_node::{
     data_node_service_server::{DataNodeService, DataNodeServiceServer},
-    BlockInfo, CreateBlocksRequest, CreateBlocksResponse, DeleteBlockRequest, EmptyResponse,
+    BlockInfo, BlockSlice, CreateBlocksRequest, CreateBlocksResponse, DeleteBlockRequest,
+    DiskUsage, DiskUsageRequest, DiskUsageResponse, EmptyResponse, RechunkBlockRequest,
+    RechunkBlockResponse, ReplicateBlockRequest,
 };
+use crate::data_node_controller::proto_data_node_api::data_node_service_api_client::DataNodeServiceApiClient;
 use crate::data_node_controller::proto_data_node_api::data_node_service_api_server::DataNodeServiceApiServer;
 use crate::data_node_controller::proto_data_node_api::{
     data_node_service_api_server::DataNodeServiceApi, ReadBlockRequest, ReadBlockResponse,
     UpdateBlockRequest, UpdateBlockResponse,
 };
-use crate::data_node_info::DataNodeInfo;
 use crate::main_server_client::MainServerClient;
 use shared::data_node_error::DataNodeError;
+use shared::hash::{Checksum, HashAlgorithm};
 use std::sync::Arc;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
@@ -31,13 +34,11 @@ pub struct DataNodeController {
 }
 
 impl DataNodeController {
-    pub async fn get_service(
-        data_node_info: DataNodeInfo,
+    pub fn get_service(
+        block_storage_service: Arc<BlockStorageService>,
         main_server_client: Arc<MainServerClient>,
-    ) -> std::io::Result<(DataNodeServiceServer<Self>, DataNodeServiceApiServer<Self>)> {
-        let block_storage_service = Arc::new(BlockStorageService::new(data_node_info).await?);
-
-        Ok((
+    ) -> (DataNodeServiceServer<Self>, DataNodeServiceApiServer<Self>) {
+        (
             DataNodeServiceServer::new(Self {
                 block_storage_service: block_storage_service.clone(),
                 main_server_client: main_server_client.clone(),
@@ -46,7 +47,120 @@ impl DataNodeController {
                 block_storage_service,
                 main_server_client,
             }),
-        ))
+        )
+    }
+
+    /// Streams the block from another data node into the already created local block.
+    async fn copy_block(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        source: &str,
+        checksum: &[u8],
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<(), DataNodeError> {
+        self.copy_range(
+            source,
+            (block_id, part),
+            None,
+            (block_id, part, 0),
+            DataNodeError::ReplicateBlockError,
+        )
+        .await?;
+
+        if !checksum.is_empty() {
+            let copied = self
+                .block_storage_service
+                .get_block_checksum(block_id, part, hash_algorithm)
+                .await?;
+
+            if copied != checksum {
+                return Err(DataNodeError::ReplicateBlockError(format!(
+                    "{}_{} has checksum {:x?} but expected {:x?}",
+                    block_id, part, copied, checksum
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the slices of blocks on other data nodes one after another into the
+    /// already created local block and returns its checksum.
+    async fn fill_block(
+        &self,
+        block_id: Uuid,
+        part: usize,
+        slices: Vec<BlockSlice>,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Checksum, DataNodeError> {
+        let mut offset = 0;
+        for slice in slices {
+            let (Some(block), Some(range)) = (slice.block, slice.range) else {
+                return Err(DataNodeError::RechunkBlockError(
+                    "Slice without block or range".to_string(),
+                ));
+            };
+            let uuid = Uuid::from_slice(&block.block_id)
+                .map_err(|_| DataNodeError::WrongUuid(format!("{:?}", &block.block_id)))?;
+
+            offset += self
+                .copy_range(
+                    &slice.source,
+                    (uuid, block.part as usize),
+                    Some(range.start as usize..range.end as usize),
+                    (block_id, part, offset),
+                    DataNodeError::RechunkBlockError,
+                )
+                .await?;
+        }
+
+        self.block_storage_service
+            .get_block_checksum(block_id, part, hash_algorithm)
+            .await
+    }
+
+    /// Streams `range` of a block on the data node at `source`, or all of it, into
+    /// the local block from `offset` and returns the number of copied bytes.
+    async fn copy_range(
+        &self,
+        source: &str,
+        (source_id, source_part): (Uuid, usize),
+        range: Option<std::ops::Range<usize>>,
+        (block_id, part, offset): (Uuid, usize, usize),
+        error: fn(String) -> DataNodeError,
+    ) -> Result<usize, DataNodeError> {
+        let mut client = DataNodeServiceApiClient::connect(format!("http://{}", source))
+            .await
+            .map_err(|err| error(format!("{}: {}", source, err)))?;
+
+        let mut stream = client
+            .read_block(ReadBlockRequest {
+                block_id: source_id.as_bytes().to_vec(),
+                part: source_part as u64,
+                range: range.map(|range| proto_data_node_api::Range {
+                    start: range.start as u64,
+                    end: range.end as u64,
+                }),
+            })
+            .await
+            .map_err(|err| error(err.message().to_string()))?
+            .into_inner();
+
+        let mut copied = 0;
+        while let Some(response) = stream
+            .message()
+            .await
+            .map_err(|err| error(err.message().to_string()))?
+        {
+            let range = offset + copied..offset + copied + response.data.len();
+            self.block_storage_service
+                .update_block(block_id, part, range, &response.data)
+                .await?;
+            copied += response.data.len();
+        }
+
+        Ok(copied)
     }
 }
 
@@ -56,9 +170,24 @@ impl DataNodeService for DataNodeController {
         &self,
         request: Request<CreateBlocksRequest>,
     ) -> Result<Response<CreateBlocksResponse>, Status> {
+        let request = request.into_inner();
+
+        let uuid = if request.block_id.is_empty() {
+            Uuid::new_v4()
+        } else {
+            Uuid::from_slice(&request.block_id)
+                .map_err(|_| DataNodeError::WrongUuid(format!("{:?}", &request.block_id)))?
+        };
+
+        let parts = if request.parts.is_empty() {
+            (0..request.count as usize).collect::<Vec<_>>()
+        } else {
+            request.parts.iter().map(|&part| part as usize).collect()
+        };
+
         let blocks = self
             .block_storage_service
-            .create_blocks(request.get_ref().count as usize)
+            .create_blocks(uuid, &parts, request.block_size as usize)
             .await?
             .into_iter()
             .map(|el| BlockInfo {
@@ -91,6 +220,115 @@ impl DataNodeService for DataNodeController {
 
         Ok(Response::new(EmptyResponse {}))
     }
+
+    async fn replicate_block(
+        &self,
+        request: Request<ReplicateBlockRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let inner = request.into_inner();
+
+        let Some(block) = inner.block else {
+            return Err(DataNodeError::ReplicateBlockError("Block are null".to_string()).into());
+        };
+
+        let uuid = Uuid::from_slice(&block.block_id)
+            .map_err(|_| DataNodeError::WrongUuid(format!("{:?}", &block.block_id)))?;
+        let part = block.part as usize;
+        let hash_algorithm = HashAlgorithm::try_from(inner.hash_algorithm)
+            .map_err(DataNodeError::UnknownHashAlgorithm)?;
+
+        self.block_storage_service
+            .create_blocks(uuid, &[part], inner.block_size as usize)
+            .await?;
+
+        if let Err(err) = self
+            .copy_block(uuid, part, &inner.source, &inner.checksum, hash_algorithm)
+            .await
+        {
+            if self
+                .block_storage_service
+                .delete_block(uuid, part)
+                .await
+                .is_err()
+            {
+                tracing::error!("Can not clean up block with id: {:x}", uuid);
+            }
+
+            return Err(err.into());
+        }
+
+        tracing::info!("Replicated block {}_{} from {}", uuid, part, inner.source);
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn rechunk_block(
+        &self,
+        request: Request<RechunkBlockRequest>,
+    ) -> Result<Response<RechunkBlockResponse>, Status> {
+        let inner = request.into_inner();
+
+        let Some(block) = inner.block else {
+            return Err(DataNodeError::RechunkBlockError("Block are null".to_string()).into());
+        };
+
+        let uuid = Uuid::from_slice(&block.block_id)
+            .map_err(|_| DataNodeError::WrongUuid(format!("{:?}", &block.block_id)))?;
+        let part = block.part as usize;
+        let hash_algorithm = HashAlgorithm::try_from(inner.hash_algorithm)
+            .map_err(DataNodeError::UnknownHashAlgorithm)?;
+        let slices = inner.slices.len();
+
+        self.block_storage_service
+            .create_blocks(uuid, &[part], inner.block_size as usize)
+            .await?;
+
+        let checksum = match self
+            .fill_block(uuid, part, inner.slices, hash_algorithm)
+            .await
+        {
+            Ok(checksum) => checksum,
+            Err(err) => {
+                if self
+                    .block_storage_service
+                    .delete_block(uuid, part)
+                    .await
+                    .is_err()
+                {
+                    tracing::error!("Can not clean up block with id: {:x}", uuid);
+                }
+
+                return Err(err.into());
+            }
+        };
+
+        tracing::info!("Filled block {}_{} from {} slices", uuid, part, slices);
+
+        Ok(Response::new(RechunkBlockResponse { checksum }))
+    }
+
+    async fn get_disk_usage(
+        &self,
+        _: Request<DiskUsageRequest>,
+    ) -> Result<Response<DiskUsageResponse>, Status> {
+        let stats = self.block_storage_service.get_stats().await;
+        let (disks, total_space) = self.block_storage_service.get_disk_usage().await;
+
+        Ok(Response::new(DiskUsageResponse {
+            disks: disks
+                .into_iter()
+                .map(|disk| DiskUsage {
+                    mount: disk.mount.to_string_lossy().to_string(),
+                    capacity: disk.capacity,
+                    used_space: disk.used_space,
+                    reserved_space: disk.reserved_space,
+                })
+                .collect(),
+            total_space,
+            used_space: stats.used_space,
+            free_space: stats.free_space,
+        }))
+    }
 }
 
 #[tonic::async_trait]
@@ -106,6 +344,10 @@ impl DataNodeServiceApi for DataNodeController {
         let uuid = Uuid::from_slice(&inner.block_id)
             .map_err(|_| DataNodeError::WrongUuid(format!("{:?}", &inner.block_id)))?;
         let part = inner.part;
+        let range = inner
+            .range
+            .as_ref()
+            .map(|range| (range.start as usize)..(range.end as usize));
 
         let (controller_tx, controller_rx) = tokio::sync::mpsc::channel(128);
         let (service_tx, service_rx) = tokio::sync::mpsc::channel(128);
@@ -113,7 +355,7 @@ impl DataNodeServiceApi for DataNodeController {
 
         tokio::spawn(async move {
             let response = block_storage
-                .read_block(uuid, part as usize, service_tx)
+                .read_block(uuid, part as usize, range, service_tx)
                 .await;
 
             if let Err(err) = response {
@@ -167,6 +409,8 @@ impl DataNodeServiceApi for DataNodeController {
         //Unsafe
         let mut block_id = Uuid::nil();
         let mut block_part = 0;
+        let mut block_hash = vec![];
+        let mut hash_algorithm = HashAlgorithm::default();
         let mut filename = String::new();
 
         while let Some(message) = inner.message().await? {
@@ -178,12 +422,24 @@ impl DataNodeServiceApi for DataNodeController {
 
             block_id = uuid;
             block_part = part as usize;
+            block_hash = message.block_hash;
+            hash_algorithm = HashAlgorithm::try_from(message.hash_algorithm)
+                .map_err(DataNodeError::UnknownHashAlgorithm)?;
             filename = message.filename;
 
             if let Some(range) = message.range {
                 let range = (range.start as usize)..(range.end as usize);
                 let data = message.data;
 
+                let hash = hash_algorithm.hash(&data);
+                if hash != message.hash {
+                    return Err(DataNodeError::ChecksumMismatch(format!(
+                        "chunk {:?} of {}_{}: got {:x?}, but client sent {:x?}",
+                        range, uuid, part, hash, message.hash
+                    ))
+                    .into());
+                }
+
                 let Ok(_) = self.block_storage_service.update_block(uuid, part as usize, range, &data).await else {
                     return Err(DataNodeError::UpdateBlockError(format!("{:?}", uuid)).into());
                 };
@@ -195,21 +451,38 @@ impl DataNodeServiceApi for DataNodeController {
 
         let checksum = self
             .block_storage_service
-            .get_block_checksum(block_id, block_part)
+            .get_block_checksum(block_id, block_part, hash_algorithm)
             .await?;
 
+        if checksum != block_hash {
+            return Err(DataNodeError::ChecksumMismatch(format!(
+                "block {}_{}: got {:x?}, but client sent {:x?}",
+                block_id, block_part, checksum, block_hash
+            ))
+            .into());
+        }
+
         self.main_server_client
             .add_checksum(
                 &filename,
                 crate::main_server_client::proto_main_server::BlockInfo {
-                    block_id: block_id.to_bytes_le().to_vec(),
+                    block_id: block_id.as_bytes().to_vec(),
                     part: block_part as u64,
                     endpoint: self.block_storage_service.get_endpoint(),
                 },
-                checksum,
+                checksum.clone(),
             )
-            .await;
+            .await
+            .map_err(|status| {
+                // The object is not committed without the checksum, so the write is not acknowledged
+                DataNodeError::ReportChecksumError(format!(
+                    "{}_{}. {}",
+                    block_id,
+                    block_part,
+                    status.message()
+                ))
+            })?;
 
-        Ok(Response::new(UpdateBlockResponse {}))
+        Ok(Response::new(UpdateBlockResponse { checksum }))
     }
 }
@@ -1,12 +1,50 @@
-use crate::config::Config;
+use crate::block_index::{block_name, parse_block_name, BlockEntry, BlockIndex};
+use crate::config::{Config, DiskPolicy, StorageEngine};
 use crate::disk_stats::DiskStats;
 use shared::data_node_error::DataNodeError;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 use sysinfo::{DiskExt, SystemExt};
 use tokio::io::SeekFrom;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct DataNodeStats {
+    pub free_space: u64,
+    pub used_space: u64,
+    pub block_count: u64,
+}
+
+/// Space of one disk available to the data node.
+#[derive(Debug)]
+pub struct DiskUsage {
+    pub mount: PathBuf,
+    pub capacity: u64,
+    pub used_space: u64,
+    pub reserved_space: u64,
+}
+
+/// Disks of the data node and its quota, shared by the pools of all block sizes.
+#[derive(Debug, Default)]
+pub struct DiskSpace {
+    /// Space of all disks limited by the `disk_space` quota
+    pub(crate) total_space: u64,
+    /// Space kept free on every disk
+    pub(crate) reserved_space: u64,
+    pub(crate) disks: Vec<DiskStats>,
+    pub(crate) disk_policy: DiskPolicy,
+    /// Disk of the next round-robin placement
+    pub(crate) next_disk: AtomicUsize,
+    /// Serializes reservations, so concurrent ones can not exceed the quota
+    pub(crate) allocation: Mutex<()>,
+}
 
 #[derive(Debug)]
 pub struct DataNodeInfo {
@@ -15,51 +53,240 @@ pub struct DataNodeInfo {
     pub(crate) working_directory: PathBuf,
     pub(crate) block_size: usize,
     pub(crate) io_buffer: usize,
-    pub(crate) total_space: u64,
-    pub(crate) disks: Vec<DiskStats>,
+    /// Disks the blocks of this pool are placed on
+    pub(crate) space: Arc<DiskSpace>,
+    /// Disk, size and checksum of every block file
+    pub(crate) block_index: BlockIndex,
 }
 
-impl DataNodeInfo {
-    pub async fn new(config: Config) -> Self {
-        let path = format!("{}_{}", config.working_directory, config.block_size);
-        let working_directory = PathBuf::from(&path);
-        let disks = Self::get_disks(config.block_size, &working_directory);
-        let total_space = disks
+/// Working directory of the pool of `block_size`, relative to a disk mount.
+fn working_directory(config: &Config, block_size: usize) -> PathBuf {
+    PathBuf::from(format!("{}_{}", config.working_directory, block_size))
+}
+
+impl DiskSpace {
+    /// Finds the disks holding the working directories of pools of `block_sizes`.
+    pub fn new(config: &Config, block_sizes: &[usize]) -> Self {
+        let working_directories = block_sizes
+            .iter()
+            .map(|&block_size| working_directory(config, block_size))
+            .collect::<Vec<_>>();
+        let disks = Self::get_disks(&working_directories);
+        let disks_space = disks
             .iter()
             .fold(0, |space, disk| space + disk.available_space);
-        let used_space = Self::get_used_space(&disks).await;
+        let total_space = config
+            .disk_space
+            .map_or(disks_space, |quota| disks_space.min(quota * 1024));
 
-        let status = Self::check_config(&config, &path).await;
-        if let Ok(status) = status {
-            if !status && used_space != 0 {
-                panic!("Wrong block size. Please recover old block size or clean up all working directories.");
+        Self::warn_unserved_pools(config, block_sizes, &disks);
+        tracing::info!(
+            "Total space: {} bytes | {} Kb | {} Mb | {} Gb",
+            total_space,
+            total_space / 1024,
+            total_space / 1024 / 1024,
+            total_space / 1024 / 1024 / 1024
+        );
+
+        Self {
+            total_space,
+            reserved_space: config.reserved_space * 1024,
+            disks,
+            disk_policy: config.disk_policy,
+            next_disk: AtomicUsize::new(0),
+            allocation: Mutex::new(()),
+        }
+    }
+
+    /// Reserves `size` bytes on a disk chosen by the disk policy which keeps its
+    /// reserve free afterwards, as long as the node stays within the quota.
+    /// Returns the index of the disk.
+    async fn reserve(&self, size: u64, name: &str) -> Result<usize, DataNodeError> {
+        let _allocation = self.allocation.lock().await;
+
+        if self.used_space().await + size > self.total_space {
+            return Err(DataNodeError::NoSpace);
+        }
+
+        for index in self.disk_order(name).await {
+            let disk = &self.disks[index];
+            let mut used_space = disk.used_space.write().await;
+            if *used_space + size + self.reserved_space <= disk.available_space {
+                *used_space += size;
+                return Ok(index);
+            }
+        }
+
+        Err(DataNodeError::NoSpace)
+    }
+
+    /// Accounts `size` bytes of blocks already stored on the disk.
+    pub(crate) async fn add_used(&self, disk: usize, size: u64) {
+        *self.disks[disk].used_space.write().await += size;
+    }
+
+    async fn release(&self, disk: usize, size: u64) {
+        let mut used_space = self.disks[disk].used_space.write().await;
+        *used_space = used_space.saturating_sub(size);
+    }
+
+    /// Indexes of the disks in the order they are tried for a new block.
+    async fn disk_order(&self, name: &str) -> Vec<usize> {
+        let count = self.disks.len();
+        if count == 0 {
+            return vec![];
+        }
+
+        let first = match self.disk_policy {
+            DiskPolicy::RoundRobin => self.next_disk.fetch_add(1, Ordering::Relaxed) % count,
+            DiskPolicy::Hash => {
+                let mut hasher = DefaultHasher::new();
+                name.hash(&mut hasher);
+                (hasher.finish() % count as u64) as usize
+            }
+            DiskPolicy::MostFree => {
+                let mut free_space = Vec::with_capacity(count);
+                for (index, disk) in self.disks.iter().enumerate() {
+                    let used_space = *disk.used_space.read().await;
+                    free_space.push((disk.available_space.saturating_sub(used_space), index));
+                }
+                free_space.sort_by_key(|&(free_space, _)| std::cmp::Reverse(free_space));
+
+                return free_space.into_iter().map(|(_, index)| index).collect();
+            }
+        };
+
+        (0..count).map(|offset| (first + offset) % count).collect()
+    }
+
+    pub(crate) async fn used_space(&self) -> u64 {
+        let mut used_space = 0;
+        for disk in &self.disks {
+            used_space += *disk.used_space.read().await
+        }
+
+        used_space
+    }
+
+    /// Space left for new blocks without touching the reserve of any disk.
+    pub(crate) async fn free_space(&self) -> u64 {
+        let mut free_space = 0;
+        for disk in &self.disks {
+            free_space += disk
+                .available_space
+                .saturating_sub(self.reserved_space)
+                .saturating_sub(*disk.used_space.read().await);
+        }
+
+        free_space.min(self.total_space.saturating_sub(self.used_space().await))
+    }
+
+    pub(crate) async fn disk_usage(&self) -> Vec<DiskUsage> {
+        let mut usage = vec![];
+        for disk in &self.disks {
+            usage.push(DiskUsage {
+                mount: disk.mount.clone(),
+                capacity: disk.available_space,
+                used_space: *disk.used_space.read().await,
+                reserved_space: self.reserved_space,
+            });
+        }
+
+        usage
+    }
+
+    /// Warns about working directories of block sizes no pool is opened for,
+    /// their blocks are neither served nor collected.
+    fn warn_unserved_pools(config: &Config, block_sizes: &[usize], disks: &[DiskStats]) {
+        let prefix = format!("{}_", config.working_directory);
+
+        for disk in disks {
+            let Ok(dir) = std::fs::read_dir(&disk.mount) else {
+                continue;
+            };
+
+            for entry in dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let Some(Ok(block_size)) = name.strip_prefix(&prefix).map(str::parse::<usize>)
+                else {
+                    continue;
+                };
+
+                if !block_sizes.contains(&block_size) {
+                    tracing::warn!(
+                        "Blocks of {} bytes in {:?} are not served. Add {} to `pool_block_sizes` until `main-server rechunk` moved them.",
+                        block_size,
+                        entry.path(),
+                        block_size
+                    );
+                }
             }
-        } else if used_space != 0 {
-            panic!("Block size was missed and file system isn't empty. Please recover old block size or clean up all working directories.");
+        }
+    }
+
+    fn get_disks(working_directories: &[PathBuf]) -> Vec<DiskStats> {
+        let mut system = sysinfo::System::new_all();
+        system.refresh_all();
+        system.sort_disks_by(|l_disk, r_disk| r_disk.available_space().cmp(&l_disk.total_space()));
+
+        system
+            .disks()
+            .iter()
+            .filter_map(|disk| {
+                DiskStats::new(
+                    disk.available_space(),
+                    disk.mount_point(),
+                    working_directories,
+                )
+            })
+            .collect()
+    }
+}
+
+impl DataNodeInfo {
+    /// Opens the pool of `config.block_size` on the disks of `space`.
+    pub async fn new(config: Config, space: Arc<DiskSpace>) -> Self {
+        let working_directory = working_directory(&config, config.block_size);
+        let path = working_directory.to_string_lossy().to_string();
+        let stored = space.disks.iter().any(|disk| {
+            std::fs::read_dir(disk.mount.join(&working_directory))
+                .is_ok_and(|mut dir| dir.next().is_some())
+        });
+
+        // The working directory is named after the block size, so its blocks have
+        // this size whatever the state says
+        let status = Self::check_config(&config, &path).await;
+        if stored && !matches!(status, Ok(true)) {
+            tracing::warn!(
+                "State of {} is missing or stale, blocks in it are taken as {} bytes blocks.",
+                path,
+                config.block_size
+            );
         }
 
         if Self::save_state(&config, &path).await.is_err() {
             tracing::warn!("Can't save the state. It can lead to memory inconsistency.");
         }
 
+        // Other storage engines keep their own index and account their blocks
+        let block_index = if config.storage_engine == StorageEngine::Files {
+            let block_index = BlockIndex::open(
+                format!(".block_index_{}", path),
+                Self::scan_blocks(&space.disks, &working_directory),
+            )
+            .await;
+            for (_, entry) in block_index.blocks().await {
+                space.add_used(entry.disk, config.block_size as u64).await;
+            }
+            block_index
+        } else {
+            BlockIndex::default()
+        };
+
         tracing::info!(
             "Working directory: {} ",
             working_directory.to_string_lossy()
         );
-        tracing::info!(
-            "Total space: {} bytes | {} Kb | {} Mb | {} Gb",
-            total_space,
-            total_space / 1024,
-            total_space / 1024 / 1024,
-            total_space / 1024 / 1024 / 1024
-        );
-        tracing::info!(
-            "Used space: {} bytes | {} Kb | {} Mb | {} Gb",
-            used_space,
-            used_space / 1024,
-            used_space / 1024 / 1024,
-            used_space / 1024 / 1024 / 1024
-        );
         tracing::info!(
             "Block size: {} bytes | {} Kb | {} Mb",
             config.block_size,
@@ -73,44 +300,71 @@ impl DataNodeInfo {
             io_buffer: config.read_buffer,
             working_directory,
             block_size: config.block_size,
-            total_space,
-            disks,
+            space,
+            block_index,
         }
     }
 
-    pub(crate) async fn get_disk_for_new_block(&self) -> Result<&DiskStats, DataNodeError> {
-        for disk in &self.disks {
-            let mut writer = disk.used_space.write().await;
-            if *writer + (self.block_size as u64) <= self.total_space {
-                *writer += self.block_size as u64;
-                return Ok(disk);
-            }
-        }
+    /// Reserves space of a new block on one of the disks. Returns the index of the
+    /// disk.
+    pub(crate) async fn reserve_block(
+        &self,
+        block_id: Uuid,
+        part: usize,
+    ) -> Result<usize, DataNodeError> {
+        let disk = self
+            .space
+            .reserve(self.block_size as u64, &block_name(block_id, part))
+            .await?;
+        self.block_index
+            .insert(block_id, part, BlockEntry::new(disk))
+            .await;
 
-        Err(DataNodeError::NoSpace)
+        Ok(disk)
+    }
+
+    /// Returns the space of the block to its disk and forgets where the block was.
+    pub(crate) async fn release_block(&self, block_id: Uuid, part: usize) {
+        if let Some(entry) = self.block_index.remove(block_id, part).await {
+            self.space.release(entry.disk, self.block_size as u64).await;
+        }
     }
 
-    pub(crate) async fn found_block<P: AsRef<Path>>(
+    pub(crate) async fn found_block(
         &self,
-        uuid: P,
+        block_id: Uuid,
+        part: usize,
     ) -> Result<PathBuf, DataNodeError> {
-        for disk in &self.disks {
-            let path = disk.mount.join(&self.working_directory).join(&uuid);
-            if path.exists() {
-                return Ok(path);
-            }
+        match self.block_index.get(block_id, part).await {
+            Some(entry) => Ok(self.block_path(entry.disk, block_id, part)),
+            None => Err(DataNodeError::BlockNotFound(format!(
+                "{} not found.",
+                block_name(block_id, part)
+            ))),
         }
+    }
 
-        Err(DataNodeError::BlockNotFound(format!(
-            "{} not found.",
-            uuid.as_ref().to_string_lossy()
-        )))
+    /// Path of the block file on the disk of `disk` index.
+    pub(crate) fn block_path(&self, disk: usize, block_id: Uuid, part: usize) -> PathBuf {
+        self.space.disks[disk]
+            .mount
+            .join(&self.working_directory)
+            .join(block_name(block_id, part))
     }
 
     pub(crate) fn get_endpoint(&self) -> String {
         format!("{}:{}", self.self_address, self.port)
     }
 
+    /// Blocks of this pool with the space of the disks shared by all pools.
+    pub(crate) async fn get_stats(&self) -> DataNodeStats {
+        DataNodeStats {
+            free_space: self.space.free_space().await,
+            used_space: self.space.used_space().await,
+            block_count: self.block_index.len().await as u64,
+        }
+    }
+
     async fn save_state(config: &Config, suffix: &str) -> std::io::Result<()> {
         let mut hasher = DefaultHasher::new();
         ((config.block_size as u64 * 2) >> 6).hash(&mut hasher);
@@ -140,31 +394,43 @@ impl DataNodeInfo {
         Ok(hash == read)
     }
 
-    async fn get_used_space(disks: &[DiskStats]) -> u64 {
-        let mut used_space = 0;
-        for disk in disks {
-            used_space += *disk.used_space.read().await
-        }
+    /// Finds every block file already stored in the working directories.
+    fn scan_blocks<P: AsRef<Path>>(
+        disks: &[DiskStats],
+        working_directory: P,
+    ) -> HashMap<(Uuid, usize), BlockEntry> {
+        let mut blocks = HashMap::new();
+        for (index, disk) in disks.iter().enumerate() {
+            let Ok(dir) = std::fs::read_dir(disk.mount.join(working_directory.as_ref())) else {
+                continue;
+            };
 
-        used_space
-    }
+            for entry in dir.flatten() {
+                let Some(block) = parse_block_name(&entry.file_name().to_string_lossy()) else {
+                    continue;
+                };
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|modified| modified.as_secs())
+                    .unwrap_or_default();
 
-    fn get_disks<P: AsRef<Path>>(block_size: usize, working_directory: P) -> Vec<DiskStats> {
-        let mut system = sysinfo::System::new_all();
-        system.refresh_all();
-        system.sort_disks_by(|l_disk, r_disk| r_disk.available_space().cmp(&l_disk.total_space()));
+                blocks.insert(
+                    block,
+                    BlockEntry {
+                        disk: index,
+                        size: metadata.len() as usize,
+                        modified,
+                        checksum: None,
+                    },
+                );
+            }
+        }
 
-        system
-            .disks()
-            .iter()
-            .filter_map(|disk| {
-                DiskStats::new(
-                    disk.available_space(),
-                    block_size,
-                    disk.mount_point(),
-                    working_directory.as_ref(),
-                )
-            })
-            .collect()
+        blocks
     }
 }
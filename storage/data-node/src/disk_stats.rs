@@ -10,11 +10,13 @@ pub(crate) struct DiskStats {
 }
 
 impl DiskStats {
+    /// Disk at `mount` holding the working directories of the pools. Bytes of
+    /// the files already stored in them count as available, pools account the
+    /// space of their blocks as used once they are opened.
     pub(crate) fn new<P: AsRef<Path>>(
         available_space: u64,
-        block_size: usize,
         mount: P,
-        working_directory: P,
+        working_directories: &[PathBuf],
     ) -> Option<Self> {
         if let Ok(metadata) = std::fs::metadata(&mount) {
             if metadata.permissions().readonly() {
@@ -24,22 +26,28 @@ impl DiskStats {
             return None;
         }
 
-        let used_space = if let Ok(dir) = std::fs::read_dir(mount.as_ref().join(working_directory))
-        {
-            (dir.into_iter().count() as u64) * (block_size as u64)
-        } else {
-            0
-        };
+        // Block files or segments and the index of the packed engine, both take
+        // only the bytes written to them
+        let stored_space = working_directories
+            .iter()
+            .filter_map(|working_directory| {
+                std::fs::read_dir(mount.as_ref().join(working_directory)).ok()
+            })
+            .flat_map(|dir| dir.flatten())
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
         let disk_stats = Self {
-            available_space: available_space + used_space,
-            used_space: RwLock::new(used_space),
+            available_space: available_space + stored_space,
+            used_space: RwLock::new(0),
             mount: mount.as_ref().to_path_buf(),
         };
 
         tracing::info!(
-            "Found disk. Info: available_space: {} bytes, used_space: {} bytes, mount: {:?}",
+            "Found disk. Info: available_space: {} bytes, stored_space: {} bytes, mount: {:?}",
             disk_stats.available_space,
-            used_space,
+            stored_space,
             mount.as_ref()
         );
 
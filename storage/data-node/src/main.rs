@@ -1,13 +1,17 @@
+use crate::block_storage::BlockStorage;
+use crate::block_storage_service::BlockStorageService;
 use crate::config::Config;
 use crate::data_node_controller::DataNodeController;
-use crate::data_node_info::DataNodeInfo;
+use crate::data_node_info::DiskSpace;
 use crate::main_server_client::MainServerClient;
 use crate::registry_client::RegistryClient;
+use crate::scrubber::Scrubber;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::thread;
 use tonic::transport::Server;
 
+mod block_backend;
+mod block_index;
 mod block_storage;
 mod block_storage_service;
 mod config;
@@ -16,6 +20,7 @@ mod data_node_info;
 mod disk_stats;
 mod main_server_client;
 mod registry_client;
+mod scrubber;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -25,19 +30,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_max_level(tracing::Level::INFO)
         .init();
 
-    let config = Config::try_from_file("DataNodeTest.toml").await;
+    let config = Config::try_from_file("DataNodeTest.toml").await?;
 
-    let main_server_client = MainServerClient::new(config.get_main_server_addr()).await;
-    let data_node_info = DataNodeInfo::new(config.clone()).await;
-    let addr = format!("{}:{}", data_node_info.self_address, data_node_info.port)
+    let main_server_client = Arc::new(MainServerClient::new(config.get_main_server_addr()).await);
+    let addr = format!("{}:{}", config.self_address, config.port)
         .parse::<SocketAddr>()
         .expect("Unable to parse socket address");
 
     let (_, health_service) = tonic_health::server::health_reporter();
+    let block_sizes = config.block_sizes();
+    let space = Arc::new(DiskSpace::new(&config, &block_sizes));
+    let mut pools = vec![];
+    for block_size in block_sizes {
+        pools.push(BlockStorage::open(config.clone(), block_size, space.clone()).await?);
+    }
+    let block_storage_service = Arc::new(BlockStorageService::with_pools(pools));
     let (data_node, data_node_api) =
-        DataNodeController::get_service(data_node_info, Arc::new(main_server_client))
-            .await
-            .unwrap();
+        DataNodeController::get_service(block_storage_service.clone(), main_server_client.clone());
+    let scrubber = Scrubber::new(&config, block_storage_service.clone(), main_server_client);
 
     tracing::info!("Starting server on {}:{}", addr.ip(), addr.port());
 
@@ -51,22 +61,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .await
     });
 
-    thread::sleep(std::time::Duration::new(10, 0));
-    let registry_client = RegistryClient::new(config.get_main_server_addr()).await;
-    match registry_client {
-        Ok(mut client) => {
-            let response = client.send_registry(&config).await;
-            if let Err(response) = response {
-                tracing::error!("{}", response.to_string());
-            }
-        }
-        Err(err) => {
-            tracing::error!(
-                "{}. Please start up the main server and restart data node.",
-                err.to_string()
-            )
-        }
-    };
+    tokio::spawn(scrubber.run());
+    tokio::spawn(RegistryClient::report_inventory(
+        config.clone(),
+        block_storage_service.clone(),
+    ));
+    tokio::spawn(RegistryClient::keep_alive(config, block_storage_service));
 
     tokio::signal::ctrl_c().await?;
     Ok(())
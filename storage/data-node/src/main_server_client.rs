@@ -2,11 +2,20 @@ pub mod proto_main_server {
     tonic::include_proto!("main_server");
 }
 
-use crate::main_server_client::proto_main_server::{AddChecksumRequest, BlockInfo};
+use crate::main_server_client::proto_main_server::{
+    AddChecksumRequest, BlockChecksum, BlockInfo, CorruptedBlockRequest, GetChecksumsRequest,
+};
 use proto_main_server::main_server_service_client::MainServerServiceClient;
+use shared::hash::Checksum;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tonic::transport::{Channel, Endpoint};
 
+/// Reports of a checksum before the write of the block fails
+const REPORT_ATTEMPTS: usize = 4;
+/// Pause after the first failed report, doubled after every next one
+const REPORT_BACKOFF: Duration = Duration::from_millis(100);
+
 pub struct MainServerClient {
     inner: RwLock<MainServerServiceClient<Channel>>,
 }
@@ -24,13 +33,76 @@ impl MainServerClient {
         }
     }
 
-    pub async fn add_checksum(&self, filename: &str, block: BlockInfo, checksum: u32) {
-        // TODO: Error handle
+    /// Reports the checksum of a written block, the report is repeated after a
+    /// backoff while the main server fails to store it.
+    pub async fn add_checksum(
+        &self,
+        filename: &str,
+        block: BlockInfo,
+        checksum: Checksum,
+    ) -> Result<(), tonic::Status> {
+        let mut backoff = REPORT_BACKOFF;
+        for _ in 1..REPORT_ATTEMPTS {
+            match self.report_checksum(filename, &block, &checksum).await {
+                Ok(_) => return Ok(()),
+                Err(status) => tracing::warn!(
+                    "Error to send checksum for {}, retrying in {:?}. {}",
+                    filename,
+                    backoff,
+                    status.message()
+                ),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+
+        self.report_checksum(filename, &block, &checksum).await
+    }
+
+    async fn report_checksum(
+        &self,
+        filename: &str,
+        block: &BlockInfo,
+        checksum: &Checksum,
+    ) -> Result<(), tonic::Status> {
+        self.inner
+            .write()
+            .await
+            .add_checksum(AddChecksumRequest {
+                filename: filename.to_string(),
+                block: Some(block.clone()),
+                checksum: checksum.clone(),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_checksums(
+        &self,
+        blocks: Vec<BlockInfo>,
+    ) -> Result<Vec<BlockChecksum>, tonic::Status> {
+        let response = self
+            .inner
+            .write()
+            .await
+            .get_checksums(GetChecksumsRequest { blocks })
+            .await?;
+
+        Ok(response.into_inner().checksums)
+    }
+
+    pub async fn report_corrupted_block(
+        &self,
+        filename: &str,
+        block: BlockInfo,
+        checksum: Checksum,
+    ) {
         let status = self
             .inner
             .write()
             .await
-            .add_checksum(AddChecksumRequest {
+            .report_corrupted_block(CorruptedBlockRequest {
                 filename: filename.to_string(),
                 block: Some(block),
                 checksum,
@@ -38,7 +110,7 @@ impl MainServerClient {
             .await;
 
         if status.is_err() {
-            tracing::error!("Error to send checksum for {}", filename);
+            tracing::error!("Error to report corrupted block of {}", filename);
         }
     }
 }
@@ -2,14 +2,19 @@ mod proto_registry_main_server {
     tonic::include_proto!("registry_main_server");
 }
 
+use std::sync::Arc;
+use std::time::Duration;
 use tonic::transport::{Channel, Endpoint};
 
 use proto_registry_main_server::{
-    registry_data_node_service_client::RegistryDataNodeServiceClient, RegistryRequest,
+    registry_data_node_service_client::RegistryDataNodeServiceClient, HeartbeatRequest,
+    InventoryRequest, RegistryRequest, StoredBlock,
 };
 use shared::data_node_error::DataNodeError;
 
 use super::config::Config;
+use crate::block_storage_service::BlockStorageService;
+use crate::data_node_info::DataNodeStats;
 
 pub struct RegistryClient {
     inner: RegistryDataNodeServiceClient<Channel>,
@@ -31,6 +36,11 @@ impl RegistryClient {
             .registry(RegistryRequest {
                 data_node_address: format!("{}:{}", config.self_address, config.port),
                 block_size: config.block_size as u64,
+                block_sizes: config
+                    .block_sizes()
+                    .into_iter()
+                    .map(|block_size| block_size as u64)
+                    .collect(),
             })
             .await;
 
@@ -43,4 +53,115 @@ impl RegistryClient {
 
         Ok(())
     }
+
+    pub async fn send_heartbeat(
+        &mut self,
+        config: &Config,
+        stats: DataNodeStats,
+    ) -> Result<(), tonic::Status> {
+        self.inner
+            .heartbeat(HeartbeatRequest {
+                data_node_address: format!("{}:{}", config.self_address, config.port),
+                free_space: stats.free_space,
+                used_space: stats.used_space,
+                block_count: stats.block_count,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn send_inventory(
+        &mut self,
+        config: &Config,
+        blocks: Vec<StoredBlock>,
+    ) -> Result<(), tonic::Status> {
+        self.inner
+            .report_inventory(InventoryRequest {
+                data_node_address: format!("{}:{}", config.self_address, config.port),
+                blocks,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Periodically reports all stored blocks, so the main server can collect the
+    /// ones no object refers to.
+    pub async fn report_inventory(config: Config, block_storage_service: Arc<BlockStorageService>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.inventory_interval));
+
+        loop {
+            interval.tick().await;
+
+            let blocks = match block_storage_service.list_blocks().await {
+                Ok(blocks) => blocks
+                    .into_iter()
+                    .map(|block| StoredBlock {
+                        block_id: block.block_id.as_bytes().to_vec(),
+                        part: block.part as u64,
+                        modified: block.modified,
+                    })
+                    .collect(),
+                Err(err) => {
+                    tracing::error!("Can not list blocks: {}", err);
+                    continue;
+                }
+            };
+
+            let mut client = match Self::new(config.get_main_server_addr()).await {
+                Ok(client) => client,
+                Err(err) => {
+                    tracing::error!("{}", err.to_string());
+                    continue;
+                }
+            };
+
+            if let Err(err) = client.send_inventory(&config, blocks).await {
+                tracing::error!("Inventory report failed: {}", err.message());
+            }
+        }
+    }
+
+    /// Registers the data node and keeps sending heartbeats. Registration is
+    /// repeated whenever the main server is unreachable or forgets the node.
+    pub async fn keep_alive(config: Config, block_storage_service: Arc<BlockStorageService>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.heartbeat_interval));
+        let mut registered: Option<Self> = None;
+
+        loop {
+            interval.tick().await;
+
+            let mut client = match registered.take() {
+                Some(client) => client,
+                None => match Self::new(config.get_main_server_addr()).await {
+                    Ok(mut client) => {
+                        if let Err(err) = client.send_registry(&config).await {
+                            tracing::error!("{}", err.to_string());
+                            continue;
+                        }
+
+                        tracing::info!("Registered on {}", config.get_main_server_addr());
+                        client
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            "{}. Waiting for the main server on {}.",
+                            err.to_string(),
+                            config.get_main_server_addr()
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            match client
+                .send_heartbeat(&config, block_storage_service.get_stats().await)
+                .await
+            {
+                Ok(_) => registered = Some(client),
+                Err(err) => tracing::error!("Heartbeat failed: {}", err.message()),
+            }
+        }
+    }
 }
@@ -0,0 +1,135 @@
+use crate::block_storage_service::BlockStorageService;
+use crate::config::Config;
+use crate::main_server_client::proto_main_server::BlockInfo;
+use crate::main_server_client::MainServerClient;
+use shared::data_node_error::DataNodeError;
+use shared::hash::HashAlgorithm;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Count of blocks whose expected checksums are requested from the main server at once.
+const SCRUB_BATCH_SIZE: usize = 64;
+
+/// Periodically re-reads every stored block, compares its checksum with the one
+/// recorded on the main server and reports mismatching copies.
+pub struct Scrubber {
+    block_storage_service: Arc<BlockStorageService>,
+    main_server_client: Arc<MainServerClient>,
+    rate: u64,
+    interval: Duration,
+}
+
+impl Scrubber {
+    pub fn new(
+        config: &Config,
+        block_storage_service: Arc<BlockStorageService>,
+        main_server_client: Arc<MainServerClient>,
+    ) -> Self {
+        Self {
+            block_storage_service,
+            main_server_client,
+            rate: config.scrub_rate,
+            interval: Duration::from_secs(config.scrub_interval),
+        }
+    }
+
+    pub async fn run(self) {
+        if self.rate == 0 {
+            tracing::info!("Scrubber is disabled");
+            return;
+        }
+
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            if let Err(err) = self.scrub().await {
+                tracing::error!("Scrubbing failed: {}", err);
+            }
+        }
+    }
+
+    /// One pass over all stored blocks, throttled to `rate` blocks per second.
+    async fn scrub(&self) -> Result<(), DataNodeError> {
+        let blocks = self.block_storage_service.list_blocks().await?;
+        let endpoint = self.block_storage_service.get_endpoint();
+        let mut throttle = tokio::time::interval(Duration::from_secs_f64(1.0 / self.rate as f64));
+        let mut corrupted = 0;
+
+        tracing::info!("Scrubbing {} blocks", blocks.len());
+
+        for batch in blocks.chunks(SCRUB_BATCH_SIZE) {
+            let request = batch
+                .iter()
+                .map(|block| BlockInfo {
+                    block_id: block.block_id.as_bytes().to_vec(),
+                    part: block.part as u64,
+                    endpoint: endpoint.clone(),
+                })
+                .collect();
+
+            let expected = match self.main_server_client.get_checksums(request).await {
+                Ok(expected) => expected,
+                Err(err) => {
+                    tracing::error!("Can not get checksums: {}", err.message());
+                    continue;
+                }
+            };
+
+            for expected in expected {
+                throttle.tick().await;
+
+                // Empty checksum means the block has not been written yet
+                let Some(block) = expected.block.filter(|_| !expected.checksum.is_empty()) else {
+                    continue;
+                };
+                let Ok(block_id) = Uuid::from_slice(&block.block_id) else {
+                    continue;
+                };
+                let Ok(hash_algorithm) = HashAlgorithm::try_from(expected.hash_algorithm) else {
+                    tracing::warn!("Unknown hash algorithm {}", expected.hash_algorithm);
+                    continue;
+                };
+
+                let checksum = match self
+                    .block_storage_service
+                    .verify_block_checksum(block_id, block.part as usize, hash_algorithm)
+                    .await
+                {
+                    Ok(checksum) => checksum,
+                    Err(err) => {
+                        tracing::warn!("{}", err);
+                        continue;
+                    }
+                };
+
+                if checksum != expected.checksum {
+                    corrupted += 1;
+                    tracing::warn!(
+                        "Block {}_{} of {} is corrupted: expected checksum {:x?}, found {:x?}",
+                        block_id,
+                        block.part,
+                        expected.filename,
+                        expected.checksum,
+                        checksum
+                    );
+
+                    self.main_server_client
+                        .report_corrupted_block(
+                            &expected.filename,
+                            BlockInfo {
+                                endpoint: endpoint.clone(),
+                                ..block
+                            },
+                            checksum,
+                        )
+                        .await;
+                }
+            }
+        }
+
+        tracing::info!("Scrubbed {} blocks, {} corrupted", blocks.len(), corrupted);
+
+        Ok(())
+    }
+}
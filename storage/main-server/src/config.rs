@@ -1,6 +1,8 @@
 use clap::Parser;
-use serde::Deserialize;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
 use std::path::Path;
+use std::str::FromStr;
 use tokio::io::AsyncReadExt;
 
 #[derive(Deserialize, Parser)]
@@ -17,16 +19,123 @@ pub struct Config {
     /// Maximum size of small file in KB
     #[arg(short, long)]
     pub(crate) max_small_file_size: usize,
-    /// Connection string for database
+    /// Where object metadata is kept: "redis", "embedded" file of a single main
+    /// server or "memory" for tests
+    #[arg(long, default_value = "redis")]
+    #[serde(default)]
+    pub(crate) metadata_backend: MetadataBackend,
+    /// Connection string for database, required by the redis backend
     #[arg(short, long)]
-    pub(crate) database_connection: String,
+    pub(crate) database_connection: Option<String>,
+    /// Snapshot file of the embedded backend, its log is kept next to it
+    #[arg(long, default_value = "metadata/objects")]
+    #[serde(default = "default_metadata_path")]
+    pub(crate) metadata_path: String,
+    /// Count of data nodes which keep a copy of every block
+    #[arg(short, long, default_value_t = 1)]
+    #[serde(default = "default_replication_factor")]
+    pub(crate) replication_factor: usize,
+    /// Seconds without heartbeat after which a data node is considered dead
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_heartbeat_timeout", deserialize_with = "positive")]
+    pub(crate) heartbeat_timeout: u64,
+    /// Interval in seconds between scans for blocks lost with dead data nodes
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(
+        default = "default_replication_check_interval",
+        deserialize_with = "positive"
+    )]
+    pub(crate) replication_check_interval: u64,
+    /// Seconds after which an unfinished multipart upload is aborted
+    #[arg(long, default_value_t = 86400)]
+    #[serde(default = "default_upload_expiry")]
+    pub(crate) upload_expiry: u64,
+    /// Interval in seconds between scans for expired multipart uploads
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_upload_gc_interval", deserialize_with = "positive")]
+    pub(crate) upload_gc_interval: u64,
+    /// Interval in seconds between collections of blocks no object refers to
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+    #[serde(default = "default_block_gc_interval", deserialize_with = "positive")]
+    pub(crate) block_gc_interval: u64,
+    /// Seconds an unreferenced block is kept after its last modification
+    #[arg(long, default_value_t = 3600)]
+    #[serde(default = "default_block_gc_grace_period")]
+    pub(crate) block_gc_grace_period: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataBackend {
+    #[default]
+    Redis,
+    Embedded,
+    Memory,
+}
+
+impl FromStr for MetadataBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redis" => Ok(MetadataBackend::Redis),
+            "embedded" => Ok(MetadataBackend::Embedded),
+            "memory" => Ok(MetadataBackend::Memory),
+            s => Err(format!("Unknown metadata backend {}", s)),
+        }
+    }
+}
+
+fn default_metadata_path() -> String {
+    "metadata/objects".to_string()
+}
+
+fn default_replication_factor() -> usize {
+    1
+}
+
+fn default_heartbeat_timeout() -> u64 {
+    15
+}
+
+fn default_replication_check_interval() -> u64 {
+    30
+}
+
+fn default_upload_expiry() -> u64 {
+    86400
+}
+
+fn default_upload_gc_interval() -> u64 {
+    3600
+}
+
+fn default_block_gc_interval() -> u64 {
+    600
+}
+
+fn default_block_gc_grace_period() -> u64 {
+    3600
+}
+
+/// Rejects zero periods of timers, `tokio::time::interval` panics on them.
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let value = u64::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(D::Error::custom("must be greater than zero"));
+    }
+
+    Ok(value)
 }
 
 impl Config {
-    pub async fn try_from_file<P: AsRef<Path>>(path: P) -> Self {
-        Self::from_file(path)
-            .await
-            .unwrap_or_else(|_| Self::parse())
+    /// Reads the config file, the command line is parsed if there is no file. A file
+    /// with a wrong value fails with `InvalidData`.
+    pub async fn try_from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        match Self::from_file(path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::parse()),
+            config => config,
+        }
     }
 
     pub async fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
@@ -38,7 +147,8 @@ impl Config {
             .await?;
         let mut buffer = String::new();
         config_file.read_to_string(&mut buffer).await?;
-        let config: Config = toml::from_str(&buffer).unwrap();
+        let config: Config = toml::from_str(&buffer)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
 
         Ok(config)
     }
@@ -1,2 +1,12 @@
+use std::time::Duration;
+
 pub const MAX_REPLICAS: usize = 8;
 pub const MAX_GROUP_ACCESS: usize = 16;
+/// Attempts of a metadata update which races with other writers of the object
+pub const UPDATE_ATTEMPTS: usize = 16;
+/// Bound of the random pause after the first lost race, doubled after every next
+/// one up to [`MAX_UPDATE_BACKOFF`]
+pub const UPDATE_BACKOFF: Duration = Duration::from_millis(1);
+pub const MAX_UPDATE_BACKOFF: Duration = Duration::from_millis(100);
+/// Largest page of a file listing, also used when the client does not set one
+pub const MAX_PAGE_SIZE: usize = 1000;
@@ -7,30 +7,83 @@ mod proto_registry {
 }
 
 use crate::data_node_client::proto_data_node::{
-    BlockInfo, CreateBlocksRequest, CreateBlocksResponse, DeleteBlockRequest,
+    BlockInfo, CreateBlocksRequest, DeleteBlockRequest, DiskUsageRequest, RechunkBlockRequest,
+    ReplicateBlockRequest,
 };
 use crate::data_node_client::proto_registry::registry_data_node_service_server::RegistryDataNodeServiceServer;
+use crate::storage_types::commit_types::block::Block;
 use proto_data_node::data_node_service_client::DataNodeServiceClient;
 use proto_registry::registry_data_node_service_server::RegistryDataNodeService;
-use proto_registry::{RegistryRequest, RegistryResponse};
+use proto_registry::{
+    HeartbeatRequest, HeartbeatResponse, InventoryRequest, InventoryResponse, RegistryRequest,
+    RegistryResponse,
+};
+use shared::hash::{Checksum, HashAlgorithm};
 use shared::main_server_error::MetadataError;
+use shared::register_client_error::RegistryError;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tonic::transport::{Channel, Endpoint};
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
-#[allow(dead_code)]
+/// Data nodes holding one part of a block, the primary goes first.
+pub struct BlockPlacement {
+    pub(crate) block_id: Uuid,
+    pub(crate) part: usize,
+    pub(crate) endpoints: Vec<String>,
+}
+
+/// Bytes `range` of a part of a block kept by the data node at `source`.
+pub struct BlockSlice {
+    pub(crate) block_id: Uuid,
+    pub(crate) part: usize,
+    pub(crate) source: String,
+    pub(crate) range: Range<usize>,
+}
+
+/// Block file reported by a data node in its inventory.
+pub struct StoredBlock {
+    pub(crate) block_id: Uuid,
+    pub(crate) part: usize,
+    /// Unix time in seconds of the last modification
+    pub(crate) modified: u64,
+}
+
+/// Liveness and disk usage of a registered data node as of its last heartbeat.
+struct DataNodeState {
+    client: DataNodeServiceClient<Channel>,
+    alive: bool,
+    last_heartbeat: Instant,
+    free_space: u64,
+    used_space: u64,
+    block_count: u64,
+    /// Block sizes of the pools served by the data node
+    block_sizes: Vec<usize>,
+    /// Latest block inventory not yet taken by the garbage collector
+    inventory: Option<Vec<StoredBlock>>,
+}
+
 pub struct DataNodeClient {
-    inner: RwLock<Option<DataNodeServiceClient<Channel>>>,
-    data_nodes: RwLock<Vec<String>>,
+    data_nodes: RwLock<BTreeMap<String, DataNodeState>>,
+    next_node: AtomicUsize,
+    /// Block size of small files
+    small_block_size: usize,
+    /// Block size of large files
+    large_block_size: usize,
 }
 
 impl DataNodeClient {
-    pub async fn new() -> Self {
+    pub async fn new(small_block_size: usize, large_block_size: usize) -> Self {
         Self {
-            inner: RwLock::new(None),
-            data_nodes: RwLock::new(vec![]),
+            data_nodes: RwLock::new(BTreeMap::new()),
+            next_node: AtomicUsize::new(0),
+            small_block_size,
+            large_block_size,
         }
     }
 
@@ -38,48 +91,402 @@ impl DataNodeClient {
         RegistryDataNodeServiceServer::from_arc(self)
     }
 
-    pub async fn create_blocks(&self, count: usize) -> Result<CreateBlocksResponse, MetadataError> {
-        if let Some(ref mut client) = *self.inner.write().await {
-            return match client
-                .create_blocks(CreateBlocksRequest {
-                    count: count as u64,
-                })
-                .await
-            {
-                Ok(block) => Ok(block.into_inner()),
-                Err(_) => Err(MetadataError::CreateBlocksResponseError(
-                    "Error from data node while creating blocks".to_string(),
-                )),
-            };
+    /// Periodically marks data nodes which have not sent a heartbeat for `timeout` as dead
+    /// and refreshes the disk usage of the alive ones.
+    pub async fn watch_heartbeats(self: Arc<Self>, timeout: Duration) {
+        let period = timeout / 2;
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            // Slow data nodes must not delay marking dead ones, the refresh gives up
+            // on them before the next one starts
+            tokio::spawn(self.clone().refresh_disk_usage(period));
+
+            for (endpoint, state) in self.data_nodes.write().await.iter_mut() {
+                if state.alive && state.last_heartbeat.elapsed() > timeout {
+                    state.alive = false;
+                    tracing::warn!(
+                        "Data node {} missed heartbeats for {:?} and is marked as dead",
+                        endpoint,
+                        timeout
+                    );
+                }
+            }
         }
+    }
 
-        Err(MetadataError::CreateFileError(
-            "No one of data nodes are connected".to_string(),
-        ))
+    /// Asks every alive data node for its disk usage, so placement does not have
+    /// to wait for the next heartbeat after blocks were created or deleted. Nodes
+    /// which do not answer within `timeout` keep the usage of their last heartbeat.
+    async fn refresh_disk_usage(self: Arc<Self>, timeout: Duration) {
+        let nodes = self
+            .data_nodes
+            .read()
+            .await
+            .iter()
+            .filter(|(_, state)| state.alive)
+            .map(|(endpoint, state)| (endpoint.clone(), state.client.clone()))
+            .collect::<Vec<_>>();
+
+        let tasks = nodes.into_iter().map(|(endpoint, mut client)| async move {
+            let response =
+                tokio::time::timeout(timeout, client.get_disk_usage(DiskUsageRequest {})).await;
+
+            (endpoint, response)
+        });
+
+        for (endpoint, response) in futures::future::join_all(tasks).await {
+            let usage = match response {
+                Ok(Ok(response)) => response.into_inner(),
+                Ok(Err(err)) => {
+                    tracing::warn!("Can not get disk usage of {}: {}", endpoint, err);
+                    continue;
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        "Disk usage of {} was not received in {:?}",
+                        endpoint,
+                        timeout
+                    );
+                    continue;
+                }
+            };
+
+            if let Some(state) = self.data_nodes.write().await.get_mut(&endpoint) {
+                state.free_space = usage.free_space;
+                state.used_space = usage.used_space;
+            }
+        }
     }
 
-    pub async fn delete_block(&self, block_id: Uuid, part: usize) -> Result<(), MetadataError> {
-        if let Some(ref mut client) = *self.inner.write().await {
-            match client
-                .delete_block(DeleteBlockRequest {
-                    block: Some(BlockInfo {
+    /// Allocates `count` parts of one block id in the pool of `block_size` and places
+    /// every part on `replication_factor` distinct data nodes, rotating the first node
+    /// between calls.
+    pub async fn create_blocks(
+        &self,
+        count: usize,
+        replication_factor: usize,
+        block_size: usize,
+    ) -> Result<Vec<BlockPlacement>, MetadataError> {
+        let nodes = self
+            .data_nodes
+            .read()
+            .await
+            .iter()
+            .filter(|(_, state)| state.alive && state.block_sizes.contains(&block_size))
+            .map(|(endpoint, state)| (endpoint.clone(), state.client.clone()))
+            .collect::<Vec<_>>();
+
+        if nodes.is_empty() {
+            return Err(MetadataError::CreateFileError(
+                "No one of data nodes are connected".to_string(),
+            ));
+        }
+
+        if nodes.len() < replication_factor {
+            return Err(MetadataError::NotEnoughDataNodes(
+                nodes.len(),
+                replication_factor,
+            ));
+        }
+
+        let block_id = Uuid::new_v4();
+        let first = self.next_node.fetch_add(count, Ordering::Relaxed);
+        let placement = (0..count)
+            .map(|part| {
+                (0..replication_factor)
+                    .map(|replica| (first + part + replica) % nodes.len())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut parts_by_node = vec![vec![]; nodes.len()];
+        for (part, node_ids) in placement.iter().enumerate() {
+            for &node_id in node_ids {
+                parts_by_node[node_id].push(part as u64);
+            }
+        }
+
+        let tasks = nodes
+            .iter()
+            .cloned()
+            .zip(parts_by_node)
+            .filter(|(_, parts)| !parts.is_empty())
+            .map(|((endpoint, mut client), parts)| async move {
+                let response = client
+                    .create_blocks(CreateBlocksRequest {
+                        count: parts.len() as u64,
                         block_id: block_id.as_bytes().to_vec(),
-                        part: part as u64,
-                    }),
+                        parts,
+                        block_size: block_size as u64,
+                    })
+                    .await;
+
+                (endpoint, response)
+            });
+        let responses = futures::future::join_all(tasks).await;
+
+        if responses.iter().all(|(_, response)| response.is_ok()) {
+            return Ok(placement
+                .into_iter()
+                .enumerate()
+                .map(|(part, node_ids)| BlockPlacement {
+                    block_id,
+                    part,
+                    endpoints: node_ids
+                        .into_iter()
+                        .map(|node_id| nodes[node_id].0.clone())
+                        .collect(),
                 })
-                .await
-            {
-                Ok(_) => {}
-                Err(info) => {
-                    tracing::error!("Error to delete block: {:?}", info)
+                .collect());
+        }
+
+        for (endpoint, response) in responses {
+            match response {
+                Ok(response) => {
+                    for block in response.into_inner().blocks {
+                        if let Err(err) = self
+                            .delete_block(&endpoint, block_id, block.part as usize)
+                            .await
+                        {
+                            tracing::error!("Can not clean up block: {}", err);
+                        }
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Error from data node {} while creating blocks: {:?}",
+                        endpoint,
+                        err
+                    );
                 }
             }
         }
 
-        Err(MetadataError::CreateFileError(
-            "No one of data nodes are connected".to_string(),
+        Err(MetadataError::CreateBlocksResponseError(
+            "Error from data node while creating blocks".to_string(),
         ))
     }
+
+    /// Connects the data node at `address` serving pools of `block_sizes`, it is
+    /// alive until it misses heartbeats.
+    pub async fn connect(
+        &self,
+        address: &str,
+        block_sizes: Vec<usize>,
+    ) -> Result<(), RegistryError> {
+        let endpoint = Endpoint::try_from(format!("http://{}", address))
+            .map_err(|_| RegistryError::DataNodeUnreachable(address.to_string()))?;
+        let channel = endpoint
+            .connect()
+            .await
+            .map_err(|_| RegistryError::DataNodeUnreachable(address.to_string()))?;
+
+        self.data_nodes.write().await.insert(
+            address.to_string(),
+            DataNodeState {
+                client: DataNodeServiceClient::new(channel),
+                alive: true,
+                last_heartbeat: Instant::now(),
+                free_space: 0,
+                used_space: 0,
+                block_count: 0,
+                block_sizes,
+                inventory: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Endpoints of registered data nodes which stopped sending heartbeats.
+    pub async fn get_dead_nodes(&self) -> HashSet<String> {
+        self.data_nodes
+            .read()
+            .await
+            .iter()
+            .filter(|(_, state)| !state.alive)
+            .map(|(endpoint, _)| endpoint.clone())
+            .collect()
+    }
+
+    /// Takes block inventories reported by alive data nodes since the previous call.
+    pub async fn take_inventories(&self) -> Vec<(String, Vec<StoredBlock>)> {
+        self.data_nodes
+            .write()
+            .await
+            .iter_mut()
+            .filter(|(_, state)| state.alive)
+            .filter_map(|(endpoint, state)| Some((endpoint.clone(), state.inventory.take()?)))
+            .collect()
+    }
+
+    /// Picks up to `count` alive data nodes serving the pool of `block_size` with the
+    /// most free space, skipping `exclude`.
+    pub async fn pick_nodes(
+        &self,
+        count: usize,
+        exclude: &[&String],
+        block_size: usize,
+    ) -> Vec<String> {
+        let data_nodes = self.data_nodes.read().await;
+        let mut candidates = data_nodes
+            .iter()
+            .filter(|(endpoint, state)| {
+                state.alive
+                    && !exclude.contains(endpoint)
+                    && state.block_sizes.contains(&block_size)
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, state)| std::cmp::Reverse(state.free_space));
+
+        candidates
+            .into_iter()
+            .take(count)
+            .map(|(endpoint, _)| endpoint.clone())
+            .collect()
+    }
+
+    /// Asks `target` to copy the block from `source` into its pool of `block_size`.
+    pub async fn replicate_block(
+        &self,
+        target: &str,
+        source: &str,
+        block: &Block<String, Checksum>,
+        hash_algorithm: HashAlgorithm,
+        block_size: usize,
+    ) -> Result<(), MetadataError> {
+        let client = self
+            .data_nodes
+            .read()
+            .await
+            .get(target)
+            .map(|state| state.client.clone());
+        let Some(mut client) = client else {
+            return Err(MetadataError::DataNodeNotFound(target.to_string()));
+        };
+
+        client
+            .replicate_block(ReplicateBlockRequest {
+                block: Some(BlockInfo {
+                    block_id: block.id.as_bytes().to_vec(),
+                    part: block.part as u64,
+                }),
+                source: source.to_string(),
+                checksum: block.checksum.clone(),
+                hash_algorithm: hash_algorithm.into(),
+                block_size: block_size as u64,
+            })
+            .await
+            .map_err(|err| {
+                MetadataError::ReplicateBlockError(format!(
+                    "{}_{} from {} to {}: {}",
+                    block.id,
+                    block.part,
+                    source,
+                    target,
+                    err.message()
+                ))
+            })?;
+
+        Ok(())
+    }
+
+    /// Asks `target` to create the part of a block in its pool of `block_size` out of
+    /// `slices` of other blocks. Returns the checksum of the new part.
+    pub async fn rechunk_block(
+        &self,
+        target: &str,
+        (block_id, part): (Uuid, usize),
+        slices: &[BlockSlice],
+        hash_algorithm: HashAlgorithm,
+        block_size: usize,
+    ) -> Result<Checksum, MetadataError> {
+        let client = self
+            .data_nodes
+            .read()
+            .await
+            .get(target)
+            .map(|state| state.client.clone());
+        let Some(mut client) = client else {
+            return Err(MetadataError::DataNodeNotFound(target.to_string()));
+        };
+
+        let response = client
+            .rechunk_block(RechunkBlockRequest {
+                block: Some(BlockInfo {
+                    block_id: block_id.as_bytes().to_vec(),
+                    part: part as u64,
+                }),
+                slices: slices
+                    .iter()
+                    .map(|slice| proto_data_node::BlockSlice {
+                        block: Some(BlockInfo {
+                            block_id: slice.block_id.as_bytes().to_vec(),
+                            part: slice.part as u64,
+                        }),
+                        source: slice.source.clone(),
+                        range: Some(proto_data_node::Range {
+                            start: slice.range.start as u64,
+                            end: slice.range.end as u64,
+                        }),
+                    })
+                    .collect(),
+                hash_algorithm: hash_algorithm.into(),
+                block_size: block_size as u64,
+            })
+            .await
+            .map_err(|err| {
+                MetadataError::RechunkBlockError(format!(
+                    "{}_{} on {}: {}",
+                    block_id,
+                    part,
+                    target,
+                    err.message()
+                ))
+            })?;
+
+        Ok(response.into_inner().checksum)
+    }
+
+    pub async fn delete_block(
+        &self,
+        endpoint: &str,
+        block_id: Uuid,
+        part: usize,
+    ) -> Result<(), MetadataError> {
+        let client = self
+            .data_nodes
+            .read()
+            .await
+            .get(endpoint)
+            .map(|state| state.client.clone());
+        let Some(mut client) = client else {
+            return Err(MetadataError::DataNodeNotFound(endpoint.to_string()));
+        };
+
+        client
+            .delete_block(DeleteBlockRequest {
+                block: Some(BlockInfo {
+                    block_id: block_id.as_bytes().to_vec(),
+                    part: part as u64,
+                }),
+            })
+            .await
+            .map_err(|err| {
+                MetadataError::DeleteBlockError(format!(
+                    "{}_{} on {}: {}",
+                    block_id,
+                    part,
+                    endpoint,
+                    err.message()
+                ))
+            })?;
+
+        Ok(())
+    }
 }
 
 #[tonic::async_trait]
@@ -88,31 +495,105 @@ impl RegistryDataNodeService for DataNodeClient {
         &self,
         request: Request<RegistryRequest>,
     ) -> Result<Response<RegistryResponse>, Status> {
-        // TODO: Переделать
         let request = request.into_inner();
         tracing::info!("Connecting {}", request.data_node_address);
 
-        let endpoint = Endpoint::try_from(format!("http://{}", request.data_node_address)).unwrap();
-        let channel = endpoint.connect().await.unwrap();
-        let client = DataNodeServiceClient::new(channel);
+        // Data nodes without pools report only their block size
+        let block_sizes = if request.block_sizes.is_empty() {
+            vec![request.block_size as usize]
+        } else {
+            request
+                .block_sizes
+                .iter()
+                .map(|&block_size| block_size as usize)
+                .collect::<Vec<_>>()
+        };
+        if let Some(&block_size) = block_sizes
+            .iter()
+            .find(|&&size| size != self.small_block_size && size != self.large_block_size)
+        {
+            return Err(RegistryError::WrongBlockSize(
+                block_size,
+                self.small_block_size,
+                self.large_block_size,
+            )
+            .into());
+        }
 
-        *self.inner.write().await = Some(client);
+        self.connect(&request.data_node_address, block_sizes.clone())
+            .await?;
 
-        tracing::info!("Connected {}", request.data_node_address);
+        tracing::info!(
+            "Connected {} with pools of {:?} bytes blocks",
+            request.data_node_address,
+            block_sizes
+        );
 
-        /*let request = request.into_inner();
+        Ok(Response::new(RegistryResponse {}))
+    }
 
-        let mut writer = self.data_nodes.write().await;
-        writer.push(request.data_node_address);
+    async fn heartbeat(
+        &self,
+        request: Request<HeartbeatRequest>,
+    ) -> Result<Response<HeartbeatResponse>, Status> {
+        let request = request.into_inner();
 
-        let endpoints = writer
-            .iter()
-            .map(|a| Channel::from_shared(a.as_bytes()).unwrap());
-        let channel = Channel::balance_list(endpoints);
+        let mut data_nodes = self.data_nodes.write().await;
+        let Some(state) = data_nodes.get_mut(&request.data_node_address) else {
+            return Err(RegistryError::UnknownDataNode(request.data_node_address).into());
+        };
 
-        let mut writer = self.inner.write().await;
-        *writer = Some(DataNodeServiceClient::new(channel));*/
+        if !state.alive {
+            tracing::info!("Data node {} is alive again", request.data_node_address);
+        }
 
-        Ok(Response::new(RegistryResponse {}))
+        state.alive = true;
+        state.last_heartbeat = Instant::now();
+        state.free_space = request.free_space;
+        state.used_space = request.used_space;
+        state.block_count = request.block_count;
+
+        tracing::debug!(
+            "Heartbeat from {}: free space {} bytes, used space {} bytes, {} blocks",
+            request.data_node_address,
+            state.free_space,
+            state.used_space,
+            state.block_count
+        );
+
+        Ok(Response::new(HeartbeatResponse {}))
+    }
+
+    async fn report_inventory(
+        &self,
+        request: Request<InventoryRequest>,
+    ) -> Result<Response<InventoryResponse>, Status> {
+        let request = request.into_inner();
+
+        let blocks = request
+            .blocks
+            .into_iter()
+            .filter_map(|block| {
+                Some(StoredBlock {
+                    block_id: Uuid::from_slice(&block.block_id).ok()?,
+                    part: block.part as usize,
+                    modified: block.modified,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut data_nodes = self.data_nodes.write().await;
+        let Some(state) = data_nodes.get_mut(&request.data_node_address) else {
+            return Err(RegistryError::UnknownDataNode(request.data_node_address).into());
+        };
+
+        tracing::debug!(
+            "Inventory from {}: {} blocks",
+            request.data_node_address,
+            blocks.len()
+        );
+        state.inventory = Some(blocks);
+
+        Ok(Response::new(InventoryResponse {}))
     }
 }
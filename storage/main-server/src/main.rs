@@ -1,14 +1,19 @@
 use crate::config::Config;
 use crate::data_node_client::DataNodeClient;
+use crate::service::garbage_collector::GarbageCollector;
+use crate::service::meta_service::MetaService;
 use crate::service::metadata_controller::MetadataController;
-use crate::service::metadata_service_redis::MetaServiceRedis;
+use crate::service::metadata_store;
+use crate::service::replication_service::ReplicationService;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tonic::transport::Server;
 
 mod config;
 mod constants;
 mod data_node_client;
+mod migration;
 mod service;
 mod storage_types;
 
@@ -19,20 +24,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_max_level(tracing::Level::INFO)
         .init();
 
-    let config = Config::try_from_file("MainServerTest.toml").await;
+    // `main-server migrate` moves objects of the flat key layout into the current one,
+    // `main-server rechunk <block_size>` copies large files into blocks of a new size
+    let args = std::env::args().collect::<Vec<_>>();
+    match args.as_slice() {
+        [_, command] if command == "migrate" => {
+            let config = Config::from_file("MainServerTest.toml").await?;
+            return Ok(migration::migrate(config).await?);
+        }
+        [_, command, block_size] if command == "rechunk" => {
+            let config = Config::from_file("MainServerTest.toml").await?;
+            return migration::rechunk(config, block_size.parse()?)
+                .await
+                .map_err(|err| err.to_string().into());
+        }
+        _ => {}
+    }
+
+    let config = Config::try_from_file("MainServerTest.toml").await?;
     let addr = format!("{}:{}", config.self_address, config.port)
         .parse::<SocketAddr>()
         .expect("Unable to parse socket address");
 
     let (_, health_service) = tonic_health::server::health_reporter();
 
-    let data_node_client = Arc::new(DataNodeClient::new().await);
-    let metadata_service_redis = {
-        let redis = redis::Client::open(config.database_connection.clone()).unwrap();
-        MetaServiceRedis::new(redis, data_node_client.clone(), config).await
-    };
+    let data_node_client =
+        Arc::new(DataNodeClient::new(config.max_small_file_size, config.block_size).await);
+    tokio::spawn(
+        data_node_client
+            .clone()
+            .watch_heartbeats(Duration::from_secs(config.heartbeat_timeout)),
+    );
+    let replication_check_interval = Duration::from_secs(config.replication_check_interval);
+    let upload_gc_interval = Duration::from_secs(config.upload_gc_interval);
+    let upload_expiry = Duration::from_secs(config.upload_expiry);
+    let block_gc_interval = Duration::from_secs(config.block_gc_interval);
+    let block_gc_grace_period = Duration::from_secs(config.block_gc_grace_period);
+    let metadata_store = metadata_store::open(&config).await;
+    let meta_service =
+        Arc::new(MetaService::new(metadata_store, data_node_client.clone(), config).await);
+    tokio::spawn(
+        meta_service
+            .clone()
+            .collect_expired_uploads(upload_gc_interval, upload_expiry),
+    );
+    let replication_service = Arc::new(ReplicationService::new(
+        meta_service.clone(),
+        data_node_client.clone(),
+    ));
+    tokio::spawn(replication_service.clone().run(replication_check_interval));
+    let garbage_collector = Arc::new(GarbageCollector::new(
+        meta_service.clone(),
+        data_node_client.clone(),
+        block_gc_grace_period,
+    ));
+    tokio::spawn(garbage_collector.run(block_gc_interval));
     let (metadata_service, metadata_service_api) =
-        MetadataController::new(Arc::new(metadata_service_redis)).await;
+        MetadataController::new(meta_service, replication_service).await;
 
     tracing::info!("Starting server on {}:{}", addr.ip(), addr.port());
     Server::builder()
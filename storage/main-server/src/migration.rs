@@ -0,0 +1,130 @@
+use crate::config::Config;
+use crate::data_node_client::DataNodeClient;
+use crate::service::meta_service::MetaService;
+use crate::service::metadata_store;
+use crate::service::metadata_store::redis_json::{scan, RedisStore, LAYOUT, LAYOUT_KEY};
+use crate::storage_types::object::Object;
+use redis::{AsyncCommands, JsonAsyncCommands, RedisResult};
+use shared::main_server_error::MetadataError;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Moves objects stored under their bare path, as before keys were namespaced by
+/// owner, into the current key layout of the redis backend. The main server must
+/// be stopped. The embedded backend moves such objects by itself on startup.
+pub async fn migrate(config: Config) -> RedisResult<()> {
+    let connection = config
+        .database_connection
+        .expect("The migration needs a database connection");
+    let client = redis::Client::open(connection)?;
+    let mut connection = client.get_async_connection().await?;
+    let store = RedisStore::new(client);
+
+    let namespace = format!("{}:", LAYOUT);
+    let keys = scan(&mut connection, "*", "ReJSON-RL")
+        .await?
+        .into_iter()
+        .filter(|key| !key.starts_with(&namespace))
+        .collect::<Vec<_>>();
+    tracing::info!(
+        "Moving {} objects into the {} key layout",
+        keys.len(),
+        LAYOUT
+    );
+
+    let mut skipped = 0;
+    for key in keys {
+        let object: String = connection.json_get(&key, ".").await?;
+        let Ok(object) = serde_json::from_str::<Object<String>>(&object) else {
+            tracing::warn!("{} is not an object and is left in place", key);
+            skipped += 1;
+            continue;
+        };
+
+        store.put(&mut connection, &object.key(), &object).await?;
+        let _: () = connection.del(&key).await?;
+    }
+
+    // Owner index of the flat layout
+    for key in scan(&mut connection, "index:*", "zset").await? {
+        let _: () = connection.del(key).await?;
+    }
+    let _: () = connection.del("index:built").await?;
+
+    if skipped == 0 {
+        let _: () = connection.set(LAYOUT_KEY, LAYOUT).await?;
+        tracing::info!("All objects use the {} key layout", LAYOUT);
+    } else {
+        tracing::warn!(
+            "{} keys are not objects, remove them and run the migration again",
+            skipped
+        );
+    }
+
+    Ok(())
+}
+
+/// Copies the blocks of all large files into blocks of `block_size` bytes, splitting
+/// or merging them, and rewrites the parts and Merkle leaves of the files. The main
+/// server must be stopped, while the data nodes keeping the files must run and
+/// serve a pool of `block_size`. Files which could not be copied keep their blocks,
+/// so the command can be run again.
+pub async fn rechunk(config: Config, block_size: usize) -> Result<(), MetadataError> {
+    let data_node_client =
+        Arc::new(DataNodeClient::new(config.max_small_file_size, config.block_size).await);
+    let store = metadata_store::open(&config).await;
+
+    let keys = store.keys("").await?;
+    tracing::info!(
+        "Re-chunking large files of {} objects into {} bytes blocks",
+        keys.len(),
+        block_size
+    );
+
+    // Data nodes are connected directly, they do not register with a stopped server
+    let mut endpoints = HashSet::new();
+    for key in &keys {
+        let Some(object) = store.get(key).await? else {
+            continue;
+        };
+        for block in object.get_all_blocks() {
+            endpoints.insert(block.dst.clone());
+            endpoints.extend(block.replicas.iter().cloned());
+        }
+    }
+    for endpoint in endpoints {
+        if let Err(err) = data_node_client.connect(&endpoint, vec![block_size]).await {
+            tracing::warn!("{}", err);
+        }
+    }
+
+    let meta_service = MetaService::new(store, data_node_client, config).await;
+    let (mut rechunked, mut failed) = (0, 0);
+    for key in keys {
+        match meta_service.rechunk_object(&key, block_size).await {
+            Ok(true) => rechunked += 1,
+            Ok(false) => {}
+            Err(err) => {
+                tracing::warn!("{} keeps its blocks. {}", key, err);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 {
+        tracing::info!(
+            "{} large files were re-chunked, all of them use {} bytes blocks now. Set `block_size` to {} on the main server, data nodes and clients.",
+            rechunked,
+            block_size,
+            block_size
+        );
+    } else {
+        tracing::warn!(
+            "{} large files were re-chunked, {} were not, fix the errors above and run the command again",
+            rechunked,
+            failed
+        );
+    }
+
+    Ok(())
+}
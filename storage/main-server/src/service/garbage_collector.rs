@@ -0,0 +1,236 @@
+use super::meta_service::MetaService;
+use super::metadata_service::{MetadataResult, MetadataService};
+use crate::data_node_client::{DataNodeClient, StoredBlock};
+use crate::storage_types::object::ObjectState;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Deletes blocks which data nodes report in their inventories, but no object
+/// refers to, e.g. leftovers of failed uploads or of failed deletions.
+pub struct GarbageCollector {
+    metadata_service: Arc<MetaService>,
+    data_node_client: Arc<DataNodeClient>,
+    grace_period: Duration,
+}
+
+impl GarbageCollector {
+    pub fn new(
+        metadata_service: Arc<MetaService>,
+        data_node_client: Arc<DataNodeClient>,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            metadata_service,
+            data_node_client,
+            grace_period,
+        }
+    }
+
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            let inventories = self.data_node_client.take_inventories().await;
+            if !inventories.is_empty() {
+                self.collect(inventories).await;
+            }
+        }
+    }
+
+    async fn collect(&self, inventories: Vec<(String, Vec<StoredBlock>)>) {
+        for (endpoint, blocks) in self.orphaned_blocks(inventories).await {
+            let mut collected = 0;
+
+            for (block_id, part) in blocks {
+                match self
+                    .data_node_client
+                    .delete_block(&endpoint, block_id, part)
+                    .await
+                {
+                    Ok(_) => collected += 1,
+                    Err(err) => tracing::error!("{}", err),
+                }
+            }
+
+            if collected > 0 {
+                tracing::info!("Collected {} orphaned blocks on {}", collected, endpoint);
+            }
+        }
+    }
+
+    /// Blocks of the inventories which no object refers to. Blocks modified within
+    /// the grace period are kept, as they may belong to an object which is being
+    /// created or to a copy which is being relocated.
+    async fn orphaned_blocks(
+        &self,
+        inventories: Vec<(String, Vec<StoredBlock>)>,
+    ) -> Vec<(String, Vec<(Uuid, usize)>)> {
+        // Referenced blocks are read after the inventories are taken, so every
+        // block created before the inventory is already visible here. Nothing is
+        // collected if they can't be read, the nodes report their blocks again.
+        let referenced = match self.referenced_blocks().await {
+            Ok(referenced) => referenced,
+            Err(err) => {
+                tracing::error!("Can't read referenced blocks. {}", err);
+                return vec![];
+            }
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        inventories
+            .into_iter()
+            .map(|(endpoint, blocks)| {
+                let orphaned = blocks
+                    .into_iter()
+                    .filter(|block| {
+                        now.saturating_sub(block.modified) >= self.grace_period.as_secs()
+                            && !referenced.contains(&(endpoint.clone(), block.block_id, block.part))
+                    })
+                    .map(|block| (block.block_id, block.part))
+                    .collect();
+                (endpoint, orphaned)
+            })
+            .collect()
+    }
+
+    /// Locations of all copies of blocks of objects which are not being deleted.
+    /// Objects left pending for longer than the grace period are removed first,
+    /// so blocks of uploads which were never finished are collected as well.
+    async fn referenced_blocks(&self) -> MetadataResult<HashSet<(String, Uuid, usize)>> {
+        let mut referenced = HashSet::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        for object in self.metadata_service.get_files("").await? {
+            if object.state == ObjectState::Deleting {
+                continue;
+            }
+
+            if object.is_abandoned(now, self.grace_period) {
+                match self
+                    .metadata_service
+                    .remove_abandoned_object(object.key(), self.grace_period)
+                    .await
+                {
+                    Ok(_) => {
+                        tracing::info!("Removed abandoned object {}", object.name);
+                        continue;
+                    }
+                    // The object was written meanwhile, its blocks are kept
+                    Err(err) => tracing::error!("{}", err),
+                }
+            }
+
+            for block in object.get_all_blocks() {
+                for endpoint in std::iter::once(&block.dst).chain(block.replicas.iter()) {
+                    referenced.insert((endpoint.clone(), block.id, block.part));
+                }
+            }
+        }
+
+        Ok(referenced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::meta_service::tests::{memory_service, store};
+    use crate::storage_types::commit_types::block::Block;
+    use crate::storage_types::commit_types::commit::Commits;
+    use crate::storage_types::commit_types::sequence::Sequence;
+    use crate::storage_types::object::{Object, ObjectVariant};
+    use crate::storage_types::small_file::SmallFile;
+    use shared::hash::HashAlgorithm;
+    use smallvec::SmallVec;
+
+    const NODE: &str = "node";
+
+    fn object(name: &'static str, state: ObjectState, block_id: Uuid) -> Object<String> {
+        let mut object = Object::new(
+            name.into(),
+            0,
+            Uuid::new_v4(),
+            HashAlgorithm::default(),
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence {
+                    seq: vec![Block {
+                        id: block_id,
+                        part: 0,
+                        dst: NODE.to_string(),
+                        replicas: SmallVec::new(),
+                        checksum: vec![],
+                    }],
+                }),
+            }),
+        );
+        object.state = state;
+        object
+    }
+
+    fn stored(block_id: Uuid, modified: u64) -> StoredBlock {
+        StoredBlock {
+            block_id,
+            part: 0,
+            modified,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_blocks() {
+        let service = Arc::new(memory_service().await);
+        let data_node_client = Arc::new(DataNodeClient::new(65536, 65536).await);
+        let collector =
+            GarbageCollector::new(service.clone(), data_node_client, Duration::from_secs(3600));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let [committed, pending, abandoned, orphaned, recent] = [(); 5].map(|_| Uuid::new_v4());
+        store(
+            &service,
+            &object("/committed", ObjectState::Committed, committed),
+        )
+        .await;
+        store(&service, &object("/pending", ObjectState::Pending, pending)).await;
+        let mut old = object("/abandoned", ObjectState::Pending, abandoned);
+        old.created_at = now - 7200;
+        store(&service, &old).await;
+
+        let inventory = [committed, pending, abandoned, orphaned]
+            .into_iter()
+            .map(|block_id| stored(block_id, now - 7200))
+            .chain(std::iter::once(stored(recent, now)))
+            .collect();
+        let mut blocks = collector
+            .orphaned_blocks(vec![(NODE.to_string(), inventory)])
+            .await;
+
+        let (endpoint, mut blocks) = blocks.pop().unwrap();
+        blocks.sort();
+        let mut expected = vec![(abandoned, 0), (orphaned, 0)];
+        expected.sort();
+        assert_eq!(endpoint, NODE);
+        assert_eq!(blocks, expected);
+
+        let names: Vec<_> = service
+            .get_files("")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.name)
+            .collect();
+        assert!(!names.iter().any(|name| name == "/abandoned"));
+        assert_eq!(names.len(), 2);
+    }
+}
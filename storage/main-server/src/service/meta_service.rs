@@ -0,0 +1,1013 @@
+use super::metadata_service::{Caller, CreationParam, Listing, MetadataResult, MetadataService};
+use super::metadata_store::MetadataStore;
+use crate::config::Config;
+use crate::constants::{
+    MAX_GROUP_ACCESS, MAX_REPLICAS, MAX_UPDATE_BACKOFF, UPDATE_ATTEMPTS, UPDATE_BACKOFF,
+};
+use crate::data_node_client::{BlockSlice, DataNodeClient};
+use crate::storage_types::commit_types::block::Block;
+use crate::storage_types::commit_types::commit::Commits;
+use crate::storage_types::commit_types::merkle_tree::MerkleTree;
+use crate::storage_types::commit_types::sequence::Sequence;
+use crate::storage_types::large_file::LargeFile;
+use crate::storage_types::object::{
+    object_key, GroupAccess, MultipartUpload, Object, ObjectState, ObjectVariant, Permission,
+    Permissions,
+};
+use crate::storage_types::small_file::SmallFile;
+use async_trait::async_trait;
+use fast_str::FastStr;
+use rand::Rng;
+use shared::hash::{Checksum, HashAlgorithm};
+use shared::main_server_error::MetadataError;
+use smallvec::SmallVec;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Metadata service over any [`MetadataStore`] backend.
+pub struct MetaService {
+    storage: Box<dyn MetadataStore>,
+    data_node_client: Arc<DataNodeClient>,
+    config: Config,
+}
+
+impl MetaService {
+    pub async fn new(
+        storage: Box<dyn MetadataStore>,
+        data_node_client: Arc<DataNodeClient>,
+        config: Config,
+    ) -> Self {
+        Self {
+            storage,
+            data_node_client,
+            config,
+        }
+    }
+
+    /// Block size of the pool the blocks of the object are kept in.
+    pub fn get_block_size(&self, object: &Object<String>) -> usize {
+        match object.inner {
+            ObjectVariant::LargeFile(ref file) if file.block_size != 0 => file.block_size,
+            ObjectVariant::LargeFile(_) => self.config.block_size,
+            ObjectVariant::SmallFile(_) => self.config.max_small_file_size,
+        }
+    }
+
+    async fn allocate_blocks(
+        &self,
+        count: usize,
+        block_size: usize,
+    ) -> MetadataResult<Vec<Block<String, Checksum>>> {
+        let placements = self
+            .data_node_client
+            .create_blocks(count, self.config.replication_factor, block_size)
+            .await?;
+
+        Ok(placements
+            .into_iter()
+            .map(|placement| {
+                let mut endpoints = placement.endpoints.into_iter();
+                Block {
+                    id: placement.block_id,
+                    part: placement.part,
+                    dst: endpoints.next().unwrap(/*Never panic*/),
+                    replicas: endpoints.collect(),
+                    checksum: Checksum::new(),
+                }
+            })
+            .collect())
+    }
+
+    async fn create_large_object<P: AsRef<Path> + Send + Sync>(
+        &self,
+        params: CreationParam<P>,
+        upload: Option<MultipartUpload>,
+    ) -> MetadataResult<Object<String>> {
+        let groups = read_access(&params.group_id)?;
+        let block_count = params.size / self.config.block_size + 1;
+        let blocks = self
+            .allocate_blocks(block_count, self.config.block_size)
+            .await?;
+
+        let mut object = Object::new(
+            params.path.as_ref().to_string_lossy().into(),
+            params.size,
+            params.user_id,
+            params.hash_algorithm,
+            ObjectVariant::LargeFile(LargeFile {
+                tree: MerkleTree::build(blocks, params.hash_algorithm),
+                block_size: self.config.block_size,
+            }),
+        );
+        object.upload = upload;
+        object.groups = groups;
+
+        self.storage.set(&object.key(), &object).await?;
+
+        Ok(object)
+    }
+
+    /// Applies `update` to the stored object and writes it back unless another
+    /// writer changed the object in between, then `update` is retried on the new copy
+    /// after a random part of the backoff, so racing writers don't retry in lockstep.
+    async fn update_object<F>(&self, key: &str, mut update: F) -> MetadataResult<Object<String>>
+    where
+        F: FnMut(&mut Object<String>) -> MetadataResult<()> + Send,
+    {
+        let mut backoff = UPDATE_BACKOFF;
+        for attempt in 0..UPDATE_ATTEMPTS {
+            if attempt > 0 {
+                let pause = rand::thread_rng().gen_range(Duration::ZERO..=backoff);
+                tokio::time::sleep(pause).await;
+                backoff = (backoff * 2).min(MAX_UPDATE_BACKOFF);
+            }
+
+            let mut object = self
+                .storage
+                .get(key)
+                .await?
+                .ok_or_else(|| MetadataError::FileNotFoundError(key.to_string()))?;
+            let version = object.version;
+            update(&mut object)?;
+            object.version = version + 1;
+
+            if self.storage.compare_and_set(key, version, &object).await? {
+                return Ok(object);
+            }
+        }
+
+        Err(MetadataError::VersionConflict(format!(
+            "{} is updated too often",
+            key
+        )))
+    }
+
+    /// Marks the object as deleting if it passes `check`, removes all copies of
+    /// its blocks and then the object itself.
+    async fn remove_object<F>(&self, key: String, check: F) -> MetadataResult<()>
+    where
+        F: Fn(&Object<String>) -> MetadataResult<()> + Send + Sync,
+    {
+        let object = self
+            .update_object(&key, |object| {
+                check(object)?;
+                object.state = ObjectState::Deleting;
+                Ok(())
+            })
+            .await?;
+
+        self.delete_blocks(object.get_all_blocks()).await;
+
+        self.storage.delete(&key).await
+    }
+
+    /// Removes all copies of `blocks` from data nodes, failures are only logged.
+    async fn delete_blocks(&self, blocks: &[Block<String, Checksum>]) {
+        for block in blocks {
+            for endpoint in std::iter::once(&block.dst).chain(block.replicas.iter()) {
+                if let Err(err) = self
+                    .data_node_client
+                    .delete_block(endpoint, block.id, block.part)
+                    .await
+                {
+                    tracing::error!("{}", err);
+                }
+            }
+        }
+    }
+
+    /// Removes an object which is abandoned for `max_age` along with its blocks.
+    pub async fn remove_abandoned_object(
+        &self,
+        key: String,
+        max_age: Duration,
+    ) -> MetadataResult<()> {
+        self.remove_object(key, |object| {
+            if !object.is_abandoned(unix_time(), max_age) {
+                return Err(MetadataError::VersionConflict(format!(
+                    "{} is not abandoned anymore",
+                    object.name
+                )));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Periodically aborts multipart uploads which were not completed within `max_age`.
+    pub async fn collect_expired_uploads(self: Arc<Self>, interval: Duration, max_age: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        let caller = |owner| Caller {
+            user_id: owner,
+            group_ids: vec![],
+        };
+
+        loop {
+            interval.tick().await;
+
+            let started_before = unix_time().saturating_sub(max_age.as_secs()) + 1;
+            let keys = match self.storage.expired_uploads(started_before).await {
+                Ok(keys) => keys,
+                Err(err) => {
+                    tracing::error!("Can't look for expired uploads. {}", err);
+                    continue;
+                }
+            };
+            for key in keys {
+                // The upload could be completed or started over after the keys were read
+                let object = match self.storage.get(&key).await {
+                    Ok(Some(object)) => object,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        tracing::error!("{}", err);
+                        continue;
+                    }
+                };
+                let Some(upload) = object
+                    .upload
+                    .as_ref()
+                    .filter(|upload| upload.started_at < started_before)
+                else {
+                    continue;
+                };
+
+                tracing::info!("Multipart upload of {} expired", object.name);
+                if let Err(err) = self
+                    .abort_multipart_upload(&caller(object.owner), object.key(), upload.id)
+                    .await
+                {
+                    tracing::error!("{}", err);
+                }
+            }
+        }
+    }
+
+    /// Copies a committed large file into blocks of `block_size`, replaces its parts
+    /// and Merkle leaves with them and removes the old blocks. Every new part is kept
+    /// by the data nodes of the old part it starts in. Returns `false` for small files
+    /// and files which already use blocks of `block_size`.
+    pub async fn rechunk_object(&self, key: &str, block_size: usize) -> MetadataResult<bool> {
+        let object = self
+            .storage
+            .get(key)
+            .await?
+            .ok_or_else(|| MetadataError::FileNotFoundError(key.to_string()))?;
+        if !matches!(object.inner, ObjectVariant::LargeFile(_))
+            || self.get_block_size(&object) == block_size
+        {
+            return Ok(false);
+        }
+        if !object.is_committed() {
+            return Err(MetadataError::VersionConflict(format!(
+                "{} is not committed",
+                object.name
+            )));
+        }
+
+        let mut blocks = vec![];
+        let mut rechunked = self
+            .copy_into_blocks(&object, block_size, &mut blocks)
+            .await;
+        if rechunked.is_ok() {
+            rechunked = self
+                .update_object(key, |stored| {
+                    if stored.version != object.version {
+                        return Err(MetadataError::VersionConflict(format!(
+                            "{} was changed while it was re-chunked",
+                            stored.name
+                        )));
+                    }
+                    stored.inner = ObjectVariant::LargeFile(LargeFile {
+                        tree: MerkleTree::build(blocks.clone(), stored.hash_algorithm),
+                        block_size,
+                    });
+                    Ok(())
+                })
+                .await
+                .map(|_| ());
+        }
+
+        if let Err(err) = rechunked {
+            self.delete_blocks(&blocks).await;
+            return Err(err);
+        }
+
+        self.delete_blocks(object.get_all_blocks()).await;
+
+        Ok(true)
+    }
+
+    /// Creates the parts of a new block of `block_size` out of the blocks of `object`
+    /// and pushes every part into `blocks` as soon as its first copy exists, so the
+    /// caller can remove them if a later one fails.
+    async fn copy_into_blocks(
+        &self,
+        object: &Object<String>,
+        block_size: usize,
+        blocks: &mut Vec<Block<String, Checksum>>,
+    ) -> MetadataResult<()> {
+        let old_blocks = object.get_all_blocks();
+        let old_block_size = self.get_block_size(object);
+        let block_id = Uuid::new_v4();
+
+        for part in 0..object.size / block_size + 1 {
+            let bytes = part * block_size..((part + 1) * block_size).min(object.size);
+            let slices = rechunked_slices(old_blocks, old_block_size, bytes.clone());
+            let Some(holder) = old_blocks
+                .iter()
+                .find(|block| block.part == bytes.start / old_block_size)
+                .or(old_blocks.last())
+            else {
+                return Err(MetadataError::FileNotFoundError(object.name.to_string()));
+            };
+
+            for endpoint in std::iter::once(&holder.dst).chain(holder.replicas.iter()) {
+                let checksum = self
+                    .data_node_client
+                    .rechunk_block(
+                        endpoint,
+                        (block_id, part),
+                        &slices,
+                        object.hash_algorithm,
+                        block_size,
+                    )
+                    .await?;
+
+                match blocks.last_mut() {
+                    Some(block) if block.part == part => {
+                        block.replicas.push(endpoint.clone());
+                        if block.checksum != checksum {
+                            return Err(MetadataError::RechunkBlockError(format!(
+                                "{}_{} has another checksum on {}",
+                                block_id, part, endpoint
+                            )));
+                        }
+                    }
+                    _ => blocks.push(Block {
+                        id: block_id,
+                        part,
+                        dst: endpoint.clone(),
+                        replicas: SmallVec::new(),
+                        checksum,
+                    }),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Slices of `blocks` of `block_size` which hold `bytes` of their file, in order.
+fn rechunked_slices(
+    blocks: &[Block<String, Checksum>],
+    block_size: usize,
+    bytes: Range<usize>,
+) -> Vec<BlockSlice> {
+    blocks
+        .iter()
+        .filter_map(|block| {
+            let start = block.part * block_size;
+            let range = bytes.start.max(start)..bytes.end.min(start + block_size);
+
+            (range.start < range.end).then(|| BlockSlice {
+                block_id: block.id,
+                part: block.part,
+                source: block.dst.clone(),
+                range: range.start - start..range.end - start,
+            })
+        })
+        .collect()
+}
+
+/// Fails if the client expects another version of the object. Version 0 means
+/// the client does not care.
+fn check_version(object: &Object<String>, expected: u64) -> MetadataResult<()> {
+    if expected != 0 && object.version != expected {
+        return Err(MetadataError::VersionConflict(format!(
+            "{} is at version {}, expected {}",
+            object.name, object.version, expected
+        )));
+    }
+
+    Ok(())
+}
+
+/// Read access of the groups a new object is shared with.
+fn read_access(group_ids: &[Uuid]) -> MetadataResult<Vec<GroupAccess>> {
+    if group_ids.len() > MAX_GROUP_ACCESS {
+        return Err(MetadataError::TooManyGroups(MAX_GROUP_ACCESS));
+    }
+
+    Ok(group_ids
+        .iter()
+        .map(|&group_id| GroupAccess {
+            group_id,
+            permissions: Permissions {
+                read: true,
+                ..Default::default()
+            },
+        })
+        .collect())
+}
+
+fn check_upload(object: &Object<String>, upload_id: Uuid) -> MetadataResult<()> {
+    match object.upload {
+        Some(ref upload) if upload.id == upload_id => Ok(()),
+        _ => Err(MetadataError::UploadNotFound(format!(
+            "{} of {}",
+            upload_id, object.name
+        ))),
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[async_trait]
+impl MetadataService for MetaService {
+    type Dst = String;
+
+    async fn create_small_file<P: AsRef<Path> + Send + Sync>(
+        &self,
+        params: CreationParam<P>,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        if params.size > self.config.max_small_file_size {
+            return Err(MetadataError::WrongSmallFileSize(
+                params.size,
+                self.config.max_small_file_size,
+            ));
+        }
+        let groups = read_access(&params.group_id)?;
+
+        let mut blocks = self
+            .allocate_blocks(1, self.config.max_small_file_size)
+            .await?;
+        let mut object = Object::new(
+            params.path.as_ref().to_string_lossy().into(),
+            params.size,
+            params.user_id,
+            params.hash_algorithm,
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence {
+                    seq: vec![blocks.remove(0)],
+                }),
+            }),
+        );
+        object.groups = groups;
+
+        self.storage.set(&object.key(), &object).await?;
+
+        Ok(object)
+    }
+
+    async fn create_large_file<P: AsRef<Path> + Send + Sync>(
+        &self,
+        params: CreationParam<P>,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        self.create_large_object(params, None).await
+    }
+
+    async fn initiate_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        params: CreationParam<P>,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        let upload = MultipartUpload {
+            id: Uuid::new_v4(),
+            started_at: unix_time(),
+        };
+
+        self.create_large_object(params, Some(upload)).await
+    }
+
+    async fn get_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+        upload_id: Uuid,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        let object = self.get_object(path.as_ref()).await?;
+        check_upload(&object, upload_id)?;
+
+        Ok(object)
+    }
+
+    async fn complete_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        upload_id: Uuid,
+        parts: &[(usize, Checksum)],
+    ) -> MetadataResult<()> {
+        let key = path.as_ref().to_string_lossy();
+        self.update_object(&key, |object| {
+            check_upload(object, upload_id)?;
+            caller.check(object, Permission::Write)?;
+
+            for block in object.get_all_blocks() {
+                if block.checksum.is_empty() {
+                    return Err(MetadataError::IncompleteUpload(format!(
+                        "Part {} is not uploaded",
+                        block.part
+                    )));
+                }
+
+                let confirmed = parts
+                    .iter()
+                    .any(|(part, checksum)| *part == block.part && *checksum == block.checksum);
+                if !confirmed {
+                    return Err(MetadataError::IncompleteUpload(format!(
+                        "Checksum of part {} does not match",
+                        block.part
+                    )));
+                }
+            }
+
+            object.upload = None;
+            object.state = ObjectState::Committed;
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn abort_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        upload_id: Uuid,
+    ) -> MetadataResult<()> {
+        let key = path.as_ref().to_string_lossy().to_string();
+        self.remove_object(key, |object| {
+            check_upload(object, upload_id)?;
+            caller.check(object, Permission::Write)
+        })
+        .await
+    }
+
+    async fn get_object<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        self.storage
+            .get(&path.as_ref().to_string_lossy())
+            .await?
+            .ok_or_else(|| {
+                MetadataError::FileNotFoundError(path.as_ref().to_string_lossy().to_string())
+            })
+    }
+
+    async fn get_small_file<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        let object = self.get_object(path.as_ref()).await?;
+        if !object.is_committed() {
+            return Err(MetadataError::FileNotFoundError(
+                path.as_ref().to_string_lossy().to_string(),
+            ));
+        }
+
+        match object.inner {
+            ObjectVariant::LargeFile(_) => Err(MetadataError::TryingToGetSmallButItLarge(
+                path.as_ref().to_string_lossy().to_string(),
+            )),
+            ObjectVariant::SmallFile(_) => Ok(object),
+        }
+    }
+
+    async fn add_commit_to_small_file<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        version: u64,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        let key = path.as_ref().to_string_lossy();
+        let check = |object: &Object<Self::Dst>| {
+            check_version(object, version)?;
+            if !object.is_committed() {
+                return Err(MetadataError::FileNotFoundError(key.to_string()));
+            }
+            caller.check(object, Permission::Write)?;
+            if let ObjectVariant::LargeFile(_) = object.inner {
+                return Err(MetadataError::CannotAddBlockToLargeFileError(
+                    key.to_string(),
+                ));
+            }
+
+            Ok(())
+        };
+        check(&self.get_object(&*key).await?)?;
+
+        // The block is left to the garbage collector if the object changes meanwhile
+        let block = self
+            .allocate_blocks(1, self.config.max_small_file_size)
+            .await?
+            .remove(0);
+
+        self.update_object(&key, |object| {
+            check(object)?;
+            if let ObjectVariant::SmallFile(ref mut file) = object.inner {
+                file.add_block(block.clone());
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_large_file<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+    ) -> MetadataResult<Object<Self::Dst>> {
+        let object = self.get_object(path.as_ref()).await?;
+        if !object.is_committed() {
+            return Err(MetadataError::FileNotFoundError(
+                path.as_ref().to_string_lossy().to_string(),
+            ));
+        }
+
+        match object.inner {
+            ObjectVariant::LargeFile(_) => Ok(object),
+            ObjectVariant::SmallFile(_) => Err(MetadataError::TryingToGetLargeButItSmall(
+                path.as_ref().to_string_lossy().to_string(),
+            )),
+        }
+    }
+
+    async fn delete_object<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        version: u64,
+    ) -> MetadataResult<()> {
+        let key = path.as_ref().to_string_lossy().to_string();
+        self.remove_object(key, |object| {
+            check_version(object, version)?;
+            caller.check(object, Permission::Delete)
+        })
+        .await
+    }
+
+    async fn set_group_access<P: AsRef<Path> + Send + Sync>(
+        &self,
+        user_id: Uuid,
+        path: P,
+        group_id: Uuid,
+        permissions: Permissions,
+    ) -> MetadataResult<()> {
+        let key = path.as_ref().to_string_lossy();
+        self.update_object(&key, |object| {
+            if object.owner != user_id {
+                return Err(MetadataError::NoPermission(key.to_string()));
+            }
+            if !object.set_group_access(group_id, permissions) {
+                return Err(MetadataError::TooManyGroups(MAX_GROUP_ACCESS));
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_checksum<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+        block_id: Uuid,
+        part: usize,
+        checksum: Checksum,
+    ) -> MetadataResult<()> {
+        self.update_object(&path.as_ref().to_string_lossy(), |object| {
+            object.update_block(block_id, part, checksum.clone());
+            object.commit_if_written();
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_files(&self, prefix: &str) -> MetadataResult<Vec<Object<Self::Dst>>> {
+        let mut files = vec![];
+
+        for key in self.storage.keys(prefix).await? {
+            // The object could be deleted after the keys were listed
+            match self.storage.get(&key).await {
+                Ok(Some(object)) => files.push(object),
+                Ok(None) => (),
+                Err(err) => tracing::error!("{}", err),
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn list_files(
+        &self,
+        caller: &Caller,
+        owner: Uuid,
+        prefix: &str,
+        delimiter: &str,
+        start_after: &str,
+        limit: usize,
+    ) -> MetadataResult<Listing<Self::Dst>> {
+        let mut objects = vec![];
+        let mut prefixes = vec![];
+        let mut start_after = start_after.to_string();
+
+        loop {
+            let paths = self
+                .storage
+                .list(owner, prefix, &start_after, limit)
+                .await?;
+            let exhausted = paths.len() < limit;
+
+            for path in paths {
+                if objects.len() + prefixes.len() == limit {
+                    return Ok(Listing {
+                        objects,
+                        prefixes,
+                        next: Some(start_after),
+                    });
+                }
+                // Skips the rest of the paths grouped under the last prefix
+                if path <= start_after {
+                    continue;
+                }
+
+                let group = (!delimiter.is_empty())
+                    .then(|| path[prefix.len()..].find(delimiter))
+                    .flatten();
+                if let Some(end) = group {
+                    let common_prefix = path[..prefix.len() + end + delimiter.len()].to_string();
+                    // Sorts after every path starting with the common prefix
+                    start_after = format!("{}{}", common_prefix, char::MAX);
+                    prefixes.push(common_prefix);
+                    continue;
+                }
+
+                start_after = path;
+                // The object could be deleted after the paths were listed
+                match self.storage.get(&object_key(owner, &start_after)).await {
+                    Ok(Some(object))
+                        if object.is_committed()
+                            && caller.check(&object, Permission::Read).is_ok() =>
+                    {
+                        objects.push(object)
+                    }
+                    Err(err) => tracing::error!("{}", err),
+                    _ => (),
+                }
+            }
+
+            if exhausted {
+                return Ok(Listing {
+                    objects,
+                    prefixes,
+                    next: None,
+                });
+            }
+        }
+    }
+
+    async fn find_blocks(
+        &self,
+        blocks: &[(Uuid, usize)],
+    ) -> MetadataResult<Vec<(FastStr, HashAlgorithm, Block<Self::Dst, Checksum>)>> {
+        let mut found = vec![];
+        let block_ids = blocks
+            .iter()
+            .map(|(block_id, _)| *block_id)
+            .collect::<Vec<_>>();
+
+        for key in self.storage.block_keys(&block_ids).await? {
+            // The object could be deleted after the keys were looked up
+            let object = match self.storage.get(&key).await {
+                Ok(Some(object)) => object,
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::error!("{}", err);
+                    continue;
+                }
+            };
+            for block in object.get_all_blocks() {
+                if blocks.contains(&(block.id, block.part)) {
+                    found.push((object.key().into(), object.hash_algorithm, block.clone()));
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    async fn relocate_block<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+        block_id: Uuid,
+        part: usize,
+        dst: Self::Dst,
+        replicas: SmallVec<[Self::Dst; MAX_REPLICAS]>,
+    ) -> MetadataResult<()> {
+        self.update_object(&path.as_ref().to_string_lossy(), |object| {
+            if let Some(block) = object
+                .get_all_blocks_mut()
+                .iter_mut()
+                .find(|block| block.id == block_id && block.part == part)
+            {
+                block.dst = dst.clone();
+                block.replicas = replicas.clone();
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::MetadataBackend;
+    use crate::service::metadata_store::memory::MemoryStore;
+
+    pub(crate) async fn memory_service() -> MetaService {
+        let config = Config {
+            self_address: "[::1]".to_string(),
+            port: 8000,
+            block_size: 65536,
+            max_small_file_size: 65536,
+            metadata_backend: MetadataBackend::Memory,
+            database_connection: None,
+            metadata_path: String::new(),
+            replication_factor: 1,
+            heartbeat_timeout: 15,
+            replication_check_interval: 30,
+            upload_expiry: 86400,
+            upload_gc_interval: 3600,
+            block_gc_interval: 600,
+            block_gc_grace_period: 3600,
+        };
+        let data_node_client = Arc::new(DataNodeClient::new(65536, 65536).await);
+
+        MetaService::new(Box::new(MemoryStore::new()), data_node_client, config).await
+    }
+
+    async fn put(service: &MetaService, name: &'static str, owner: Uuid, state: ObjectState) {
+        let mut object = Object::new(
+            name.into(),
+            0,
+            owner,
+            HashAlgorithm::default(),
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence { seq: vec![] }),
+            }),
+        );
+        object.state = state;
+        store(service, &object).await;
+    }
+
+    pub(crate) async fn store(service: &MetaService, object: &Object<String>) {
+        service.storage.set(&object.key(), object).await.unwrap();
+    }
+
+    fn caller(user_id: Uuid, group_ids: Vec<Uuid>) -> Caller {
+        Caller { user_id, group_ids }
+    }
+
+    fn names(listing: &Listing<String>) -> Vec<&str> {
+        listing.objects.iter().map(|object| &*object.name).collect()
+    }
+
+    #[test]
+    fn test_rechunked_slices() {
+        let blocks = (0..3)
+            .map(|part| Block {
+                id: Uuid::nil(),
+                part,
+                dst: format!("node{}", part),
+                replicas: SmallVec::new(),
+                checksum: Checksum::new(),
+            })
+            .collect::<Vec<_>>();
+        let slices = |bytes| {
+            rechunked_slices(&blocks, 4, bytes)
+                .into_iter()
+                .map(|slice| (slice.part, slice.source, slice.range))
+                .collect::<Vec<_>>()
+        };
+
+        // Split into blocks of 3 bytes
+        assert_eq!(
+            slices(3..6),
+            vec![
+                (0, "node0".to_string(), 3..4),
+                (1, "node1".to_string(), 0..2)
+            ]
+        );
+        // Merged into blocks of 8 bytes
+        assert_eq!(slices(8..10), vec![(2, "node2".to_string(), 0..2)]);
+        assert_eq!(
+            slices(0..8),
+            vec![
+                (0, "node0".to_string(), 0..4),
+                (1, "node1".to_string(), 0..4)
+            ]
+        );
+        assert!(slices(10..10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_files_pages_and_delimiter() {
+        let service = memory_service().await;
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        for name in ["/a/1", "/a/2", "/b/1", "/c", "/d/x/1"] {
+            put(&service, name, owner, ObjectState::Committed).await;
+        }
+        put(&service, "/a/3", other, ObjectState::Committed).await;
+        put(&service, "/a/4", owner, ObjectState::Pending).await;
+
+        let owner_caller = caller(owner, vec![]);
+
+        let listing = service
+            .list_files(&owner_caller, owner, "/a", "", "", 10)
+            .await
+            .unwrap();
+        assert_eq!(names(&listing), vec!["/a/1", "/a/2"]);
+        assert!(listing.next.is_none());
+
+        let first = service
+            .list_files(&owner_caller, owner, "/", "/", "", 2)
+            .await
+            .unwrap();
+        assert!(first.objects.is_empty());
+        assert_eq!(first.prefixes, vec!["/a/", "/b/"]);
+
+        let token = first.next.unwrap();
+        let second = service
+            .list_files(&owner_caller, owner, "/", "/", &token, 2)
+            .await
+            .unwrap();
+        assert_eq!(names(&second), vec!["/c"]);
+        assert_eq!(second.prefixes, vec!["/d/"]);
+        assert!(second.next.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_group_access() {
+        let service = memory_service().await;
+        let (owner, member, group) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        put(&service, "/shared", owner, ObjectState::Committed).await;
+        let key = object_key(owner, "/shared");
+        let member_caller = caller(member, vec![group]);
+
+        let listing = service
+            .list_files(&member_caller, owner, "/", "", "", 10)
+            .await
+            .unwrap();
+        assert!(listing.objects.is_empty());
+
+        let read = Permissions {
+            read: true,
+            ..Default::default()
+        };
+        assert!(service
+            .set_group_access(member, &key, group, read)
+            .await
+            .is_err());
+        service
+            .set_group_access(owner, &key, group, read)
+            .await
+            .unwrap();
+
+        let listing = service
+            .list_files(&member_caller, owner, "/", "", "", 10)
+            .await
+            .unwrap();
+        assert_eq!(names(&listing), vec!["/shared"]);
+        assert!(service
+            .delete_object(&member_caller, &key, 0)
+            .await
+            .is_err());
+
+        service
+            .set_group_access(owner, &key, group, Permissions::default())
+            .await
+            .unwrap();
+        let listing = service
+            .list_files(&member_caller, owner, "/", "", "", 10)
+            .await
+            .unwrap();
+        assert!(listing.objects.is_empty());
+    }
+}
@@ -6,36 +6,46 @@ mod proto_main_server_api {
     tonic::include_proto!("main_server_api");
 }
 
-use crate::service::metadata_service::{CreationParam, MetadataService};
-use crate::service::metadata_service_redis::MetaServiceRedis;
-use crate::storage_types::object::ObjectVariant;
+use crate::constants::MAX_PAGE_SIZE;
+use crate::service::meta_service::MetaService;
+use crate::service::metadata_service::{Caller, CreationParam, MetadataService};
+use crate::service::replication_service::ReplicationService;
+use crate::storage_types::commit_types::block::Block;
+use crate::storage_types::object::{object_key, ObjectVariant, Permission, Permissions};
 use proto_main_server::main_server_service_server::MainServerService;
 use proto_main_server::main_server_service_server::MainServerServiceServer;
-use proto_main_server::AddChecksumRequest;
+use proto_main_server::BlockInfo as BlockInfoInternal;
 use proto_main_server::EmptyResponse as EmptyResponseInternal;
+use proto_main_server::{
+    AddChecksumRequest, BlockChecksum, CorruptedBlockRequest, GetChecksumsRequest,
+    GetChecksumsResponse,
+};
 use proto_main_server_api::main_server_service_api_server::{
     MainServerServiceApi, MainServerServiceApiServer,
 };
 use proto_main_server_api::{
-    AddCommitSmallFileRequest, BlockInfo, CreateFileRequest, CreateLargeFileResponse,
-    CreateSmallFileResponse, DeleteFileRequest, EmptyResponse, FileRequest, FileResponse,
-    GetLargeFileRequest, GetSmallFileLastVersionRequest, GetSmallFileRequest, LargeFileResponse,
-    ObjectResponse,
+    AddCommitSmallFileRequest, BlockInfo, CompleteMultipartUploadRequest, CreateFileRequest,
+    CreateLargeFileResponse, CreateSmallFileResponse, DeleteFileRequest, EmptyResponse,
+    FileRequest, FileResponse, GetLargeFileRequest, GetSmallFileLastVersionRequest,
+    GetSmallFileRequest, GrantAccessRequest, InitiateMultipartUploadResponse, LargeFileResponse,
+    ListUploadedPartsResponse, MultipartUploadRequest, ObjectResponse, RevokeAccessRequest,
+    UploadPartRequest,
 };
+use shared::hash::{Checksum, HashAlgorithm};
 use shared::main_server_error::MetadataError;
-use smallvec::SmallVec;
 use std::sync::Arc;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
-use zerocopy::AsBytes;
 
 pub struct MetadataController {
-    metadata_service: Arc<MetaServiceRedis>,
+    metadata_service: Arc<MetaService>,
+    replication_service: Arc<ReplicationService>,
 }
 
 impl MetadataController {
     pub async fn new(
-        service: Arc<MetaServiceRedis>,
+        service: Arc<MetaService>,
+        replication_service: Arc<ReplicationService>,
     ) -> (
         MainServerServiceServer<Self>,
         MainServerServiceApiServer<Self>,
@@ -43,14 +53,60 @@ impl MetadataController {
         (
             MainServerServiceServer::new(Self {
                 metadata_service: service.clone(),
+                replication_service: replication_service.clone(),
             }),
             MainServerServiceApiServer::new(Self {
                 metadata_service: service,
+                replication_service,
             }),
         )
     }
 }
 
+fn parse_uuid(id: &[u8]) -> Result<Uuid, MetadataError> {
+    Uuid::from_slice(id).map_err(|_| MetadataError::WrongUuid(format!("{:?}", id)))
+}
+
+fn caller(user_id: &[u8], group_ids: &[Vec<u8>]) -> Result<Caller, MetadataError> {
+    Ok(Caller {
+        user_id: parse_uuid(user_id)?,
+        group_ids: group_ids
+            .iter()
+            .map(|id| parse_uuid(id))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Owner addressed by a request, the caller if `owner_id` is empty.
+fn owner(caller: &Caller, owner_id: &[u8]) -> Result<Uuid, MetadataError> {
+    if owner_id.is_empty() {
+        Ok(caller.user_id)
+    } else {
+        parse_uuid(owner_id)
+    }
+}
+
+/// Key of the object at `filename` of the addressed owner.
+fn target_key(caller: &Caller, owner_id: &[u8], filename: &str) -> Result<String, MetadataError> {
+    Ok(object_key(owner(caller, owner_id)?, filename))
+}
+
+impl From<(&Block<String, Checksum>, HashAlgorithm, &str)> for BlockInfo {
+    fn from(
+        (block, hash_algorithm, object_key): (&Block<String, Checksum>, HashAlgorithm, &str),
+    ) -> Self {
+        Self {
+            block_id: block.id.as_bytes().to_vec(),
+            part: block.part as u64,
+            endpoint: block.dst.clone(),
+            replicas: block.replicas.to_vec(),
+            checksum: block.checksum.clone(),
+            hash_algorithm: hash_algorithm.into(),
+            object_key: object_key.to_string(),
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl MainServerServiceApi for MetadataController {
     async fn create_small_file(
@@ -65,20 +121,24 @@ impl MainServerServiceApi for MetadataController {
             .metadata_service
             .create_small_file(CreationParam {
                 user_id,
-                group_id: SmallVec::new(),
+                group_id: request
+                    .share_with_group_ids
+                    .iter()
+                    .map(|id| parse_uuid(id))
+                    .collect::<Result<_, _>>()?,
                 path: request.filename,
                 size: request.size as usize,
+                hash_algorithm: HashAlgorithm::try_from(request.hash_algorithm)
+                    .map_err(MetadataError::UnknownHashAlgorithm)?,
             })
             .await?;
 
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
         if let ObjectVariant::SmallFile(file) = file.inner {
             let block = file.commits.last();
             Ok(Response::new(CreateSmallFileResponse {
-                block: Some(BlockInfo {
-                    block_id: block.id.as_bytes().to_vec(),
-                    part: block.part as u64,
-                    endpoint: block.dst.clone(),
-                }),
+                block: Some((block, hash_algorithm, key.as_str()).into()),
             }))
         } else {
             unreachable!()
@@ -97,22 +157,26 @@ impl MainServerServiceApi for MetadataController {
             .metadata_service
             .create_large_file(CreationParam {
                 user_id,
-                group_id: SmallVec::new(),
+                group_id: request
+                    .share_with_group_ids
+                    .iter()
+                    .map(|id| parse_uuid(id))
+                    .collect::<Result<_, _>>()?,
                 path: request.filename,
                 size: request.size as usize,
+                hash_algorithm: HashAlgorithm::try_from(request.hash_algorithm)
+                    .map_err(MetadataError::UnknownHashAlgorithm)?,
             })
             .await?;
 
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
         if let ObjectVariant::LargeFile(file) = file.inner {
             let blocks = file
                 .tree
                 .leaves()
                 .iter()
-                .map(|el| BlockInfo {
-                    block_id: el.id.as_bytes().to_vec(),
-                    part: el.part as u64,
-                    endpoint: el.dst.clone(),
-                })
+                .map(|block| (block, hash_algorithm, key.as_str()).into())
                 .collect();
 
             Ok(Response::new(CreateLargeFileResponse { blocks }))
@@ -126,25 +190,21 @@ impl MainServerServiceApi for MetadataController {
         request: Request<GetSmallFileRequest>,
     ) -> Result<Response<BlockInfo>, Status> {
         let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
 
         let file = self
             .metadata_service
-            .get_small_file(request.filename.clone())
+            .get_small_file(target_key(&caller, &request.owner_id, &request.filename)?)
             .await?;
+        caller.check(&file, Permission::Read)?;
 
-        if file.owner.as_bytes() != request.user_id.as_bytes() {
-            return Err(MetadataError::NoPermission(request.filename).into());
-        }
-
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
         if let ObjectVariant::SmallFile(file) = file.inner {
             let block = file.commits.index(request.index as usize);
 
             if let Some(block) = block {
-                Ok(Response::new(BlockInfo {
-                    block_id: block.id.as_bytes().to_vec(),
-                    part: block.part as u64,
-                    endpoint: block.dst.clone(),
-                }))
+                Ok(Response::new((block, hash_algorithm, key.as_str()).into()))
             } else {
                 Err(MetadataError::WrongSmallFileVersion(request.filename).into())
             }
@@ -158,23 +218,22 @@ impl MainServerServiceApi for MetadataController {
         request: Request<GetSmallFileLastVersionRequest>,
     ) -> Result<Response<BlockInfo>, Status> {
         let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
 
         let file = self
             .metadata_service
-            .get_small_file(request.filename.clone())
+            .get_small_file(target_key(&caller, &request.owner_id, &request.filename)?)
             .await?;
+        caller.check(&file, Permission::Read)?;
 
-        if file.owner.as_bytes() != request.user_id.as_bytes() {
-            return Err(MetadataError::NoPermission(request.filename).into());
-        }
-
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
         if let ObjectVariant::SmallFile(file) = file.inner {
-            let block = file.commits.last();
-            Ok(Response::new(BlockInfo {
-                block_id: block.id.as_bytes().to_vec(),
-                part: block.part as u64,
-                endpoint: block.dst.clone(),
-            }))
+            if let Some(block) = file.commits.last_written() {
+                Ok(Response::new((block, hash_algorithm, key.as_str()).into()))
+            } else {
+                Err(MetadataError::FileNotFoundError(request.filename).into())
+            }
         } else {
             unreachable!()
         }
@@ -185,23 +244,22 @@ impl MainServerServiceApi for MetadataController {
         request: Request<AddCommitSmallFileRequest>,
     ) -> Result<Response<BlockInfo>, Status> {
         let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
 
         let file = self
             .metadata_service
-            .add_commit_to_small_file(request.filename.clone())
+            .add_commit_to_small_file(
+                &caller,
+                target_key(&caller, &request.owner_id, &request.filename)?,
+                request.version,
+            )
             .await?;
 
-        if file.owner.as_bytes() != request.user_id.as_bytes() {
-            return Err(MetadataError::NoPermission(request.filename).into());
-        }
-
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
         if let ObjectVariant::SmallFile(file) = file.inner {
             let block = file.commits.last();
-            Ok(Response::new(BlockInfo {
-                block_id: block.id.as_bytes().to_vec(),
-                part: block.part as u64,
-                endpoint: block.dst.clone(),
-            }))
+            Ok(Response::new((block, hash_algorithm, key.as_str()).into()))
         } else {
             unreachable!()
         }
@@ -212,29 +270,33 @@ impl MainServerServiceApi for MetadataController {
         request: Request<GetLargeFileRequest>,
     ) -> Result<Response<LargeFileResponse>, Status> {
         let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
 
         let file = self
             .metadata_service
-            .get_large_file(request.filename.clone())
+            .get_large_file(target_key(&caller, &request.owner_id, &request.filename)?)
             .await?;
+        caller.check(&file, Permission::Read)?;
 
-        if file.owner.as_bytes() != request.user_id.as_bytes() {
-            return Err(MetadataError::NoPermission(request.filename).into());
-        }
-
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
+        let version = file.version;
+        let block_size = self.metadata_service.get_block_size(&file);
         if let ObjectVariant::LargeFile(file) = file.inner {
             let blocks = file
                 .tree
                 .leaves()
                 .iter()
-                .map(|el| BlockInfo {
-                    block_id: el.id.as_bytes().to_vec(),
-                    part: el.part as u64,
-                    endpoint: el.dst.clone(),
-                })
+                .map(|block| (block, hash_algorithm, key.as_str()).into())
                 .collect();
 
-            Ok(Response::new(LargeFileResponse { blocks }))
+            Ok(Response::new(LargeFileResponse {
+                blocks,
+                root: file.tree.root().clone(),
+                hash_algorithm: hash_algorithm.into(),
+                version,
+                block_size: block_size as u64,
+            }))
         } else {
             unreachable!()
         }
@@ -245,12 +307,14 @@ impl MainServerServiceApi for MetadataController {
         request: Request<DeleteFileRequest>,
     ) -> Result<Response<EmptyResponse>, Status> {
         let request = request.into_inner();
-
-        let user_id = Uuid::from_slice(&request.user_id)
-            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &request.user_id)))?;
+        let caller = caller(&request.user_id, &request.group_ids)?;
 
         self.metadata_service
-            .delete_object(user_id, request.filename)
+            .delete_object(
+                &caller,
+                target_key(&caller, &request.owner_id, &request.filename)?,
+                request.version,
+            )
             .await?;
 
         Ok(Response::new(EmptyResponse {}))
@@ -261,21 +325,236 @@ impl MainServerServiceApi for MetadataController {
         request: Request<FileRequest>,
     ) -> Result<Response<FileResponse>, Status> {
         let request = request.into_inner();
-        let objects = self.metadata_service.get_files(&request.prefix).await;
+        let caller = caller(&request.user_id, &request.group_ids)?;
+        let owner = owner(&caller, &request.owner_id)?;
+        let page_size = match request.page_size as usize {
+            0 => MAX_PAGE_SIZE,
+            page_size => page_size.min(MAX_PAGE_SIZE),
+        };
+
+        let listing = self
+            .metadata_service
+            .list_files(
+                &caller,
+                owner,
+                &request.prefix,
+                &request.delimiter,
+                &request.continuation_token,
+                page_size,
+            )
+            .await?;
 
-        let objects = objects
+        let objects = listing
+            .objects
             .into_iter()
-            .filter(|el| el.owner.as_bytes() == request.user_id.as_bytes())
             .map(|el| ObjectResponse {
                 filename: el.name.to_string(),
                 r#type: match el.inner {
                     ObjectVariant::SmallFile(_) => 0,
                     ObjectVariant::LargeFile(_) => 1,
                 },
+                version: el.version,
+            })
+            .collect();
+
+        Ok(Response::new(FileResponse {
+            files: objects,
+            common_prefixes: listing.prefixes,
+            next_continuation_token: listing.next.unwrap_or_default(),
+        }))
+    }
+
+    async fn initiate_multipart_upload(
+        &self,
+        request: Request<CreateFileRequest>,
+    ) -> Result<Response<InitiateMultipartUploadResponse>, Status> {
+        let request = request.into_inner();
+        let user_id = Uuid::from_slice(&request.user_id)
+            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &request.user_id)))?;
+
+        let file = self
+            .metadata_service
+            .initiate_multipart_upload(CreationParam {
+                user_id,
+                group_id: request
+                    .share_with_group_ids
+                    .iter()
+                    .map(|id| parse_uuid(id))
+                    .collect::<Result<_, _>>()?,
+                path: request.filename,
+                size: request.size as usize,
+                hash_algorithm: HashAlgorithm::try_from(request.hash_algorithm)
+                    .map_err(MetadataError::UnknownHashAlgorithm)?,
             })
+            .await?;
+
+        let hash_algorithm = file.hash_algorithm;
+        let key = file.key();
+        let upload_id = file.upload.as_ref().unwrap(/*Never panic*/).id;
+        let blocks = file
+            .get_all_blocks()
+            .iter()
+            .map(|block| (block, hash_algorithm, key.as_str()).into())
             .collect();
 
-        Ok(Response::new(FileResponse { files: objects }))
+        Ok(Response::new(InitiateMultipartUploadResponse {
+            upload_id: upload_id.as_bytes().to_vec(),
+            blocks,
+        }))
+    }
+
+    async fn upload_part(
+        &self,
+        request: Request<UploadPartRequest>,
+    ) -> Result<Response<BlockInfo>, Status> {
+        let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
+        let upload_id = Uuid::from_slice(&request.upload_id)
+            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &request.upload_id)))?;
+
+        let file = self
+            .metadata_service
+            .get_multipart_upload(
+                target_key(&caller, &request.owner_id, &request.filename)?,
+                upload_id,
+            )
+            .await?;
+        caller.check(&file, Permission::Write)?;
+
+        let block = file
+            .get_all_blocks()
+            .iter()
+            .find(|block| block.part as u64 == request.part)
+            .ok_or_else(|| {
+                MetadataError::UploadNotFound(format!(
+                    "Part {} of {}",
+                    request.part, request.filename
+                ))
+            })?;
+
+        Ok(Response::new(
+            (block, file.hash_algorithm, file.key().as_str()).into(),
+        ))
+    }
+
+    async fn list_uploaded_parts(
+        &self,
+        request: Request<MultipartUploadRequest>,
+    ) -> Result<Response<ListUploadedPartsResponse>, Status> {
+        let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
+        let upload_id = Uuid::from_slice(&request.upload_id)
+            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &request.upload_id)))?;
+
+        let file = self
+            .metadata_service
+            .get_multipart_upload(
+                target_key(&caller, &request.owner_id, &request.filename)?,
+                upload_id,
+            )
+            .await?;
+        caller.check(&file, Permission::Write)?;
+
+        // Data nodes report the checksum once the whole part is written
+        let key = file.key();
+        let parts = file
+            .get_all_blocks()
+            .iter()
+            .filter(|block| !block.checksum.is_empty())
+            .map(|block| (block, file.hash_algorithm, key.as_str()).into())
+            .collect();
+
+        Ok(Response::new(ListUploadedPartsResponse { parts }))
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        request: Request<CompleteMultipartUploadRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
+        let upload_id = Uuid::from_slice(&request.upload_id)
+            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &request.upload_id)))?;
+
+        let parts = request
+            .parts
+            .into_iter()
+            .map(|part| (part.part as usize, part.checksum))
+            .collect::<Vec<_>>();
+
+        self.metadata_service
+            .complete_multipart_upload(
+                &caller,
+                target_key(&caller, &request.owner_id, &request.filename)?,
+                upload_id,
+                &parts,
+            )
+            .await?;
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        request: Request<MultipartUploadRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        let caller = caller(&request.user_id, &request.group_ids)?;
+        let upload_id = Uuid::from_slice(&request.upload_id)
+            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &request.upload_id)))?;
+
+        self.metadata_service
+            .abort_multipart_upload(
+                &caller,
+                target_key(&caller, &request.owner_id, &request.filename)?,
+                upload_id,
+            )
+            .await?;
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn grant_access(
+        &self,
+        request: Request<GrantAccessRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        let user_id = parse_uuid(&request.user_id)?;
+        let permissions = request.permissions.unwrap_or_default();
+
+        self.metadata_service
+            .set_group_access(
+                user_id,
+                object_key(user_id, &request.filename),
+                parse_uuid(&request.group_id)?,
+                Permissions {
+                    read: permissions.read,
+                    write: permissions.write,
+                    delete: permissions.delete,
+                },
+            )
+            .await?;
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn revoke_access(
+        &self,
+        request: Request<RevokeAccessRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        let user_id = parse_uuid(&request.user_id)?;
+
+        self.metadata_service
+            .set_group_access(
+                user_id,
+                object_key(user_id, &request.filename),
+                parse_uuid(&request.group_id)?,
+                Permissions::default(),
+            )
+            .await?;
+
+        Ok(Response::new(EmptyResponse {}))
     }
 }
 
@@ -295,7 +574,64 @@ impl MainServerService for MetadataController {
                 block.part as usize,
                 request.checksum,
             )
-            .await;
+            .await?;
+
+        Ok(Response::new(EmptyResponseInternal {}))
+    }
+
+    async fn get_checksums(
+        &self,
+        request: Request<GetChecksumsRequest>,
+    ) -> Result<Response<GetChecksumsResponse>, Status> {
+        let request = request.into_inner();
+
+        let mut blocks = Vec::with_capacity(request.blocks.len());
+        for block in request.blocks {
+            let block_id = Uuid::from_slice(&block.block_id)
+                .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &block.block_id)))?;
+            blocks.push((block_id, block.part as usize));
+        }
+
+        let checksums = self
+            .metadata_service
+            .find_blocks(&blocks)
+            .await?
+            .into_iter()
+            .map(|(filename, hash_algorithm, block)| BlockChecksum {
+                filename: filename.to_string(),
+                block: Some(BlockInfoInternal {
+                    block_id: block.id.as_bytes().to_vec(),
+                    part: block.part as u64,
+                    endpoint: block.dst,
+                }),
+                checksum: block.checksum,
+                hash_algorithm: hash_algorithm.into(),
+            })
+            .collect();
+
+        Ok(Response::new(GetChecksumsResponse { checksums }))
+    }
+
+    async fn report_corrupted_block(
+        &self,
+        request: Request<CorruptedBlockRequest>,
+    ) -> Result<Response<EmptyResponseInternal>, Status> {
+        let request = request.into_inner();
+        let Some(block) = request.block else {
+            return Err(Status::invalid_argument("Block are null"));
+        };
+
+        let block_id = Uuid::from_slice(&block.block_id)
+            .map_err(|_| MetadataError::WrongUuid(format!("{:?}", &block.block_id)))?;
+
+        self.replication_service
+            .quarantine_block(
+                &request.filename,
+                block_id,
+                block.part as usize,
+                &block.endpoint,
+            )
+            .await?;
 
         Ok(Response::new(EmptyResponseInternal {}))
     }
@@ -1,7 +1,10 @@
-use crate::constants::MAX_GROUP_ACCESS;
-use crate::storage_types::object::Object;
+use crate::constants::{MAX_GROUP_ACCESS, MAX_REPLICAS};
+use crate::storage_types::commit_types::block::Block;
+use crate::storage_types::object::{Object, Permission, Permissions};
 use async_trait::async_trait;
+use fast_str::FastStr;
 use serde::Serialize;
+use shared::hash::{Checksum, HashAlgorithm};
 use smallvec::SmallVec;
 use std::fmt::Debug;
 use std::path::Path;
@@ -9,6 +12,9 @@ use uuid::Uuid;
 
 pub type MetadataResult<T> = Result<T, shared::main_server_error::MetadataError>;
 
+/// Objects are created at a path of their owner and then addressed by their key,
+/// see [`Object::key`], so different owners can have objects at the same path.
+/// Other users access an object through the groups it is shared with.
 #[async_trait]
 pub trait MetadataService: Send + Sync + Sync {
     type Dst: Serialize + Debug;
@@ -23,14 +29,52 @@ pub trait MetadataService: Send + Sync + Sync {
         params: CreationParam<P>,
     ) -> MetadataResult<Object<Self::Dst>>;
 
+    /// Creates a large file hidden from readers until the returned session is completed.
+    async fn initiate_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        params: CreationParam<P>,
+    ) -> MetadataResult<Object<Self::Dst>>;
+
+    async fn get_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+        upload_id: Uuid,
+    ) -> MetadataResult<Object<Self::Dst>>;
+
+    /// Publishes the file if every block has a checksum reported by its data node
+    /// that matches the one calculated by the client.
+    async fn complete_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        upload_id: Uuid,
+        parts: &[(usize, Checksum)],
+    ) -> MetadataResult<()>;
+
+    async fn abort_multipart_upload<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        upload_id: Uuid,
+    ) -> MetadataResult<()>;
+
+    async fn get_object<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+    ) -> MetadataResult<Object<Self::Dst>>;
+
     async fn get_small_file<P: AsRef<Path> + Send + Sync>(
         &self,
         path: P,
     ) -> MetadataResult<Object<Self::Dst>>;
 
+    /// Adds a block for the next version of the file. `version` is the version
+    /// of the object the client has read, 0 adds the block unconditionally.
     async fn add_commit_to_small_file<P: AsRef<Path> + Send + Sync>(
         &self,
+        caller: &Caller,
         path: P,
+        version: u64,
     ) -> MetadataResult<Object<Self::Dst>>;
 
     async fn get_large_file<P: AsRef<Path> + Send + Sync>(
@@ -38,28 +82,108 @@ pub trait MetadataService: Send + Sync + Sync {
         path: P,
     ) -> MetadataResult<Object<Self::Dst>>;
 
+    /// Removes the object if it is still at `version`, 0 removes any version.
     async fn delete_object<P: AsRef<Path> + Send + Sync>(
+        &self,
+        caller: &Caller,
+        path: P,
+        version: u64,
+    ) -> MetadataResult<()>;
+
+    /// Sets what members of `group_id` may do with the object, no permissions
+    /// revoke the access of the group. Only the owner can share the object.
+    async fn set_group_access<P: AsRef<Path> + Send + Sync>(
         &self,
         user_id: Uuid,
         path: P,
+        group_id: Uuid,
+        permissions: Permissions,
     ) -> MetadataResult<()>;
 
+    /// Fails if the object is not updated, the data node reports the checksum again.
     async fn add_checksum<P: AsRef<Path> + Send + Sync>(
         &self,
         path: P,
         block_id: Uuid,
         part: usize,
-        checksum: u32,
-    );
-    async fn get_files(&self, prefix: &str) -> Vec<Object<Self::Dst>>;
+        checksum: Checksum,
+    ) -> MetadataResult<()>;
+
+    /// All objects starting with `prefix`, used by background scans
+    async fn get_files(&self, prefix: &str) -> MetadataResult<Vec<Object<Self::Dst>>>;
+
+    /// Page of committed objects of `owner` the caller may read starting with
+    /// `prefix`, in lexicographic order after `start_after`. Objects with `delimiter`
+    /// after the prefix are grouped by the part of their name up to the delimiter.
+    async fn list_files(
+        &self,
+        caller: &Caller,
+        owner: Uuid,
+        prefix: &str,
+        delimiter: &str,
+        start_after: &str,
+        limit: usize,
+    ) -> MetadataResult<Listing<Self::Dst>>;
+
+    /// Looks up referenced blocks by id and part, returns them with the key and
+    /// the hash algorithm of their object.
+    async fn find_blocks(
+        &self,
+        blocks: &[(Uuid, usize)],
+    ) -> MetadataResult<Vec<(FastStr, HashAlgorithm, Block<Self::Dst, Checksum>)>>;
+
+    async fn relocate_block<P: AsRef<Path> + Send + Sync>(
+        &self,
+        path: P,
+        block_id: Uuid,
+        part: usize,
+        dst: Self::Dst,
+        replicas: SmallVec<[Self::Dst; MAX_REPLICAS]>,
+    ) -> MetadataResult<()>;
+}
+
+/// Page of objects returned by [`MetadataService::list_files`].
+#[derive(Debug)]
+pub struct Listing<T: Serialize + Debug> {
+    pub objects: Vec<Object<T>>,
+    /// Names up to and including the delimiter, one for all objects under them
+    pub prefixes: Vec<String>,
+    /// Where the next page starts, `None` on the last page
+    pub next: Option<String>,
+}
+
+/// User a request is made by, with the groups the user is a member of.
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub user_id: Uuid,
+    pub group_ids: Vec<Uuid>,
+}
+
+impl Caller {
+    /// Fails unless the caller owns the object or is in a group allowed `permission`.
+    pub fn check<T: Serialize + Debug>(
+        &self,
+        object: &Object<T>,
+        permission: Permission,
+    ) -> MetadataResult<()> {
+        if !object.allows(self.user_id, &self.group_ids, permission) {
+            return Err(shared::main_server_error::MetadataError::NoPermission(
+                object.name.to_string(),
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub struct CreationParam<P: AsRef<Path>> {
     pub user_id: Uuid,
+    /// Groups the new object is shared with for reading
     pub group_id: SmallVec<[Uuid; MAX_GROUP_ACCESS]>,
     pub path: P,
     pub size: usize,
+    pub hash_algorithm: HashAlgorithm,
 }
 
 unsafe impl<P: AsRef<Path>> Send for CreationParam<P> {}
@@ -0,0 +1,367 @@
+use super::{key_range, list_owned, BlockIndex, MetadataStore, UploadIndex};
+use crate::service::metadata_service::MetadataResult;
+use crate::storage_types::object::{parse_object_key, Object};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use shared::main_server_error::MetadataError;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Log records after which the snapshot is rewritten
+const COMPACTION_RECORDS: usize = 4096;
+
+/// Line of the append-only log. The snapshot holds only `Set` records.
+#[derive(Serialize, Deserialize)]
+enum Record {
+    Set { key: String, object: Object<String> },
+    Delete { key: String },
+}
+
+struct State {
+    objects: BTreeMap<String, Object<String>>,
+    blocks: BlockIndex,
+    uploads: UploadIndex,
+    log: Option<File>,
+    /// Records appended to the log since the snapshot was written
+    log_records: usize,
+}
+
+/// Objects of a single main server kept in a local file. Every change is appended
+/// to a log and synced before it is visible, the log is folded into a snapshot on
+/// startup and after every [`COMPACTION_RECORDS`] changes.
+pub struct EmbeddedStore {
+    /// Path of the snapshot, the log has the `.log` extension added
+    path: PathBuf,
+    state: RwLock<State>,
+}
+
+impl EmbeddedStore {
+    pub async fn open<P: Into<PathBuf>>(path: P) -> std::io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut objects = BTreeMap::new();
+        for file in [path.clone(), Self::log_path(&path)] {
+            match tokio::fs::read_to_string(&file).await {
+                Ok(buffer) => Self::replay(&buffer, &mut objects),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        // Objects stored under their bare path before keys were namespaced by owner
+        let legacy = objects
+            .keys()
+            .filter(|key| parse_object_key(key).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in legacy {
+            let object = objects.remove(&key).unwrap(/*Never panic*/);
+            objects.insert(object.key(), object);
+        }
+        let mut blocks = BlockIndex::default();
+        let mut uploads = UploadIndex::default();
+        for (key, object) in &objects {
+            blocks.insert(key, object);
+            uploads.update(key, None, Some(object));
+        }
+        tracing::info!(
+            "Loaded {} objects from {}",
+            objects.len(),
+            path.to_string_lossy()
+        );
+
+        let store = Self {
+            path,
+            state: RwLock::new(State {
+                objects,
+                blocks,
+                uploads,
+                log: None,
+                log_records: 0,
+            }),
+        };
+        store.compact(&mut *store.state.write().await).await?;
+
+        Ok(store)
+    }
+
+    /// Appends the record to the log and syncs it. The caller applies the change
+    /// to the objects only if it succeeds.
+    async fn append(&self, state: &mut State, record: &Record) -> MetadataResult<()> {
+        // The log is closed if a write failed or it could not be reopened, a new
+        // snapshot of the objects starts it over
+        if state.log.is_none() {
+            self.compact(state).await.map_err(|err| {
+                MetadataError::StorageError(format!("Metadata log is not open. {}", err))
+            })?;
+        }
+        let log = state.log.as_mut().unwrap(/*Never panic*/);
+
+        let mut line = serde_json::to_vec(record).unwrap();
+        line.push(b'\n');
+        if let Err(err) = Self::write_synced(log, &line).await {
+            // A part of the record can be written, later records must not follow it
+            state.log = None;
+            return Err(MetadataError::StorageError(format!(
+                "Can't append to the metadata log. {}",
+                err
+            )));
+        }
+        state.log_records += 1;
+
+        if state.log_records > COMPACTION_RECORDS.max(state.objects.len()) {
+            if let Err(err) = self.compact(state).await {
+                tracing::error!("Can't compact the metadata log: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes all objects to a new snapshot and starts an empty log.
+    async fn compact(&self, state: &mut State) -> std::io::Result<()> {
+        state.log = None;
+
+        let mut buffer = vec![];
+        for (key, object) in &state.objects {
+            let record = Record::Set {
+                key: key.clone(),
+                object: object.clone(),
+            };
+            serde_json::to_writer(&mut buffer, &record).unwrap();
+            buffer.push(b'\n');
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        let mut snapshot = File::create(&tmp).await?;
+        Self::write_synced(&mut snapshot, &buffer).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        let log = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(Self::log_path(&self.path))
+            .await?;
+        state.log = Some(log);
+        state.log_records = 0;
+
+        Ok(())
+    }
+
+    async fn write_synced(file: &mut File, buffer: &[u8]) -> std::io::Result<()> {
+        file.write_all(buffer).await?;
+        file.sync_data().await
+    }
+
+    fn replay(buffer: &str, objects: &mut BTreeMap<String, Object<String>>) {
+        for line in buffer.lines() {
+            // Only the last line can be torn by a crash, its change was never acknowledged
+            let Ok(record) = serde_json::from_str::<Record>(line) else {
+                tracing::warn!("Skipping torn metadata log record");
+                break;
+            };
+
+            match record {
+                Record::Set { key, object } => {
+                    objects.insert(key, object);
+                }
+                Record::Delete { key } => {
+                    objects.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn log_path(path: &Path) -> PathBuf {
+        let mut log = path.as_os_str().to_owned();
+        log.push(".log");
+        log.into()
+    }
+}
+
+#[async_trait]
+impl MetadataStore for EmbeddedStore {
+    async fn get(&self, key: &str) -> MetadataResult<Option<Object<String>>> {
+        Ok(self.state.read().await.objects.get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, object: &Object<String>) -> MetadataResult<()> {
+        let mut state = self.state.write().await;
+        let record = Record::Set {
+            key: key.to_string(),
+            object: object.clone(),
+        };
+        self.append(&mut state, &record).await?;
+
+        let stored = state.objects.insert(key.to_string(), object.clone());
+        state.blocks.insert(key, object);
+        state.uploads.update(key, stored.as_ref(), Some(object));
+
+        Ok(())
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: &str,
+        version: u64,
+        object: &Object<String>,
+    ) -> MetadataResult<bool> {
+        let mut state = self.state.write().await;
+        if !matches!(state.objects.get(key), Some(stored) if stored.version == version) {
+            return Ok(false);
+        }
+        let record = Record::Set {
+            key: key.to_string(),
+            object: object.clone(),
+        };
+        self.append(&mut state, &record).await?;
+
+        let stored = state.objects.insert(key.to_string(), object.clone());
+        state.blocks.insert(key, object);
+        state.uploads.update(key, stored.as_ref(), Some(object));
+
+        Ok(true)
+    }
+
+    async fn delete(&self, key: &str) -> MetadataResult<()> {
+        let mut state = self.state.write().await;
+        if !state.objects.contains_key(key) {
+            return Ok(());
+        }
+        let record = Record::Delete {
+            key: key.to_string(),
+        };
+        self.append(&mut state, &record).await?;
+
+        let object = state.objects.remove(key).unwrap(/*Never panic*/);
+        state.blocks.remove(&object);
+        state.uploads.update(key, Some(&object), None);
+
+        Ok(())
+    }
+
+    async fn keys(&self, prefix: &str) -> MetadataResult<Vec<String>> {
+        Ok(key_range(&self.state.read().await.objects, prefix, "")
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+
+    async fn block_keys(&self, block_ids: &[Uuid]) -> MetadataResult<Vec<String>> {
+        Ok(self.state.read().await.blocks.find(block_ids))
+    }
+
+    async fn expired_uploads(&self, started_before: u64) -> MetadataResult<Vec<String>> {
+        Ok(self
+            .state
+            .read()
+            .await
+            .uploads
+            .started_before(started_before))
+    }
+
+    async fn list(
+        &self,
+        owner: Uuid,
+        prefix: &str,
+        start_after: &str,
+        limit: usize,
+    ) -> MetadataResult<Vec<String>> {
+        let state = self.state.read().await;
+        Ok(list_owned(
+            &state.objects,
+            owner,
+            prefix,
+            start_after,
+            limit,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_types::commit_types::commit::Commits;
+    use crate::storage_types::commit_types::sequence::Sequence;
+    use crate::storage_types::object::{object_key, ObjectVariant};
+    use crate::storage_types::small_file::SmallFile;
+    use shared::hash::HashAlgorithm;
+
+    fn object(owner: Uuid, name: &'static str, size: usize) -> Object<String> {
+        Object::new(
+            name.into(),
+            size,
+            owner,
+            HashAlgorithm::default(),
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence { seq: vec![] }),
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_embedded_store_reopen() {
+        let path = std::env::temp_dir()
+            .join(format!("metadata_{}", Uuid::new_v4()))
+            .join("objects");
+        let owner = Uuid::new_v4();
+        let key = |path| object_key(owner, path);
+
+        let store = EmbeddedStore::open(&path).await.unwrap();
+        store
+            .set(&key("/a/1"), &object(owner, "/a/1", 1))
+            .await
+            .unwrap();
+        store
+            .set(&key("/a/2"), &object(owner, "/a/2", 2))
+            .await
+            .unwrap();
+        store
+            .set(&key("/b/1"), &object(owner, "/b/1", 3))
+            .await
+            .unwrap();
+        store
+            .set(&key("/a/2"), &object(owner, "/a/2", 4))
+            .await
+            .unwrap();
+        store.delete(&key("/a/1")).await.unwrap();
+        // Stored under the bare path as before keys were namespaced
+        store.set("/c", &object(owner, "/c", 5)).await.unwrap();
+        drop(store);
+
+        // A crash in the middle of an append leaves a torn line at the end of the log
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(EmbeddedStore::log_path(&path))
+            .await
+            .unwrap();
+        log.write_all(b"{\"Delete\":{\"ke").await.unwrap();
+        drop(log);
+
+        let store = EmbeddedStore::open(&path).await.unwrap();
+        assert!(store.get(&key("/a/1")).await.unwrap().is_none());
+        assert_eq!(store.get(&key("/a/2")).await.unwrap().unwrap().size, 4);
+        assert_eq!(store.keys(&key("/a")).await.unwrap(), vec![key("/a/2")]);
+        assert!(store.get("/c").await.unwrap().is_none());
+        assert_eq!(
+            store.list(owner, "/", "", 10).await.unwrap(),
+            vec!["/a/2", "/b/1", "/c"]
+        );
+        assert!(store
+            .list(Uuid::new_v4(), "", "", 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        tokio::fs::remove_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+    }
+}
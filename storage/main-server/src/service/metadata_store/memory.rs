@@ -0,0 +1,236 @@
+use super::{key_range, list_owned, BlockIndex, MetadataStore, UploadIndex};
+use crate::service::metadata_service::MetadataResult;
+use crate::storage_types::object::Object;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Objects kept in memory only, lost on restart. Meant for tests and local runs.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: RwLock<State>,
+}
+
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<String, Object<String>>,
+    blocks: BlockIndex,
+    uploads: UploadIndex,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MetadataStore for MemoryStore {
+    async fn get(&self, key: &str) -> MetadataResult<Option<Object<String>>> {
+        Ok(self.state.read().await.objects.get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, object: &Object<String>) -> MetadataResult<()> {
+        let mut state = self.state.write().await;
+        state.blocks.insert(key, object);
+        let stored = state.objects.insert(key.to_string(), object.clone());
+        state.uploads.update(key, stored.as_ref(), Some(object));
+
+        Ok(())
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: &str,
+        version: u64,
+        object: &Object<String>,
+    ) -> MetadataResult<bool> {
+        let mut state = self.state.write().await;
+        let stored = match state.objects.get_mut(key) {
+            Some(stored) if stored.version == version => std::mem::replace(stored, object.clone()),
+            _ => return Ok(false),
+        };
+        state.blocks.insert(key, object);
+        state.uploads.update(key, Some(&stored), Some(object));
+
+        Ok(true)
+    }
+
+    async fn delete(&self, key: &str) -> MetadataResult<()> {
+        let mut state = self.state.write().await;
+        if let Some(object) = state.objects.remove(key) {
+            state.blocks.remove(&object);
+            state.uploads.update(key, Some(&object), None);
+        }
+
+        Ok(())
+    }
+
+    async fn keys(&self, prefix: &str) -> MetadataResult<Vec<String>> {
+        Ok(key_range(&self.state.read().await.objects, prefix, "")
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+
+    async fn block_keys(&self, block_ids: &[Uuid]) -> MetadataResult<Vec<String>> {
+        Ok(self.state.read().await.blocks.find(block_ids))
+    }
+
+    async fn expired_uploads(&self, started_before: u64) -> MetadataResult<Vec<String>> {
+        Ok(self
+            .state
+            .read()
+            .await
+            .uploads
+            .started_before(started_before))
+    }
+
+    async fn list(
+        &self,
+        owner: Uuid,
+        prefix: &str,
+        start_after: &str,
+        limit: usize,
+    ) -> MetadataResult<Vec<String>> {
+        let state = self.state.read().await;
+        Ok(list_owned(
+            &state.objects,
+            owner,
+            prefix,
+            start_after,
+            limit,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_types::commit_types::block::Block;
+    use crate::storage_types::commit_types::commit::Commits;
+    use crate::storage_types::commit_types::sequence::Sequence;
+    use crate::storage_types::object::{MultipartUpload, ObjectVariant};
+    use crate::storage_types::small_file::SmallFile;
+    use shared::hash::HashAlgorithm;
+    use smallvec::SmallVec;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_compare_and_set_keeps_concurrent_updates() {
+        let store = Arc::new(MemoryStore::new());
+        let object = Object::new(
+            "/counter".into(),
+            0,
+            Uuid::new_v4(),
+            HashAlgorithm::default(),
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence { seq: vec![] }),
+            }),
+        );
+        store.set("/counter", &object).await.unwrap();
+
+        let mut writers = vec![];
+        for _ in 0..16 {
+            let store = store.clone();
+            writers.push(tokio::spawn(async move {
+                loop {
+                    let mut object = store.get("/counter").await.unwrap().unwrap();
+                    let version = object.version;
+                    object.size += 1;
+                    object.version += 1;
+                    tokio::task::yield_now().await;
+                    if store
+                        .compare_and_set("/counter", version, &object)
+                        .await
+                        .unwrap()
+                    {
+                        break;
+                    }
+                }
+            }));
+        }
+        futures::future::join_all(writers).await;
+
+        let object = store.get("/counter").await.unwrap().unwrap();
+        assert_eq!(object.size, 16);
+        assert_eq!(object.version, 17);
+        assert!(!store.compare_and_set("/counter", 1, &object).await.unwrap());
+        assert!(!store.compare_and_set("/missing", 1, &object).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_block_keys_follow_changes() {
+        let store = MemoryStore::new();
+        let block = |id| Block {
+            id,
+            part: 0,
+            dst: String::new(),
+            replicas: SmallVec::new(),
+            checksum: vec![],
+        };
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut object = Object::new(
+            "/file".into(),
+            0,
+            Uuid::new_v4(),
+            HashAlgorithm::default(),
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence {
+                    seq: vec![block(first)],
+                }),
+            }),
+        );
+        store.set("/file", &object).await.unwrap();
+        assert_eq!(
+            store.block_keys(&[first, second]).await.unwrap(),
+            vec!["/file"]
+        );
+
+        if let ObjectVariant::SmallFile(ref mut file) = object.inner {
+            file.add_block(block(second));
+        }
+        object.version += 1;
+        assert!(store.compare_and_set("/file", 1, &object).await.unwrap());
+        assert_eq!(store.block_keys(&[second]).await.unwrap(), vec!["/file"]);
+
+        store.delete("/file").await.unwrap();
+        assert!(store.block_keys(&[first, second]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expired_uploads_follow_changes() {
+        let store = MemoryStore::new();
+        let mut object = Object::new(
+            "/upload".into(),
+            0,
+            Uuid::new_v4(),
+            HashAlgorithm::default(),
+            ObjectVariant::SmallFile(SmallFile {
+                commits: Commits::Sequence(Sequence { seq: vec![] }),
+            }),
+        );
+        object.upload = Some(MultipartUpload {
+            id: Uuid::new_v4(),
+            started_at: 100,
+        });
+        store.set("/upload", &object).await.unwrap();
+        store.set("/other", &object).await.unwrap();
+        assert!(store.expired_uploads(100).await.unwrap().is_empty());
+        assert_eq!(
+            store.expired_uploads(101).await.unwrap(),
+            vec!["/other", "/upload"]
+        );
+
+        // Completed uploads and removed objects are not indexed anymore
+        let mut completed = object.clone();
+        completed.upload = None;
+        completed.version += 1;
+        assert!(store
+            .compare_and_set("/upload", object.version, &completed)
+            .await
+            .unwrap());
+        store.delete("/other").await.unwrap();
+        assert!(store.expired_uploads(u64::MAX).await.unwrap().is_empty());
+    }
+}
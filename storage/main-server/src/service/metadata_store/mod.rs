@@ -0,0 +1,174 @@
+pub mod embedded;
+pub mod memory;
+pub mod redis_json;
+
+use crate::config::{Config, MetadataBackend};
+use crate::service::metadata_service::MetadataResult;
+use crate::service::metadata_store::embedded::EmbeddedStore;
+use crate::service::metadata_store::memory::MemoryStore;
+use crate::service::metadata_store::redis_json::RedisStore;
+use crate::storage_types::object::{object_key, Object};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use uuid::Uuid;
+
+/// Key-value storage of object metadata. Objects are kept under keys built by
+/// [`object_key`], so every owner has a namespace of its own.
+#[async_trait]
+pub trait MetadataStore: Send + Sync {
+    /// Fails if the object can't be read, `None` if there is no object at `key`.
+    async fn get(&self, key: &str) -> MetadataResult<Option<Object<String>>>;
+
+    /// Fails if the object is not stored.
+    async fn set(&self, key: &str, object: &Object<String>) -> MetadataResult<()>;
+
+    /// Stores `object` only if the stored object is still at `version`. Returns
+    /// `false` if it was changed or removed in the meantime.
+    async fn compare_and_set(
+        &self,
+        key: &str,
+        version: u64,
+        object: &Object<String>,
+    ) -> MetadataResult<bool>;
+
+    /// Fails if the object is not removed, removing a missing object succeeds.
+    async fn delete(&self, key: &str) -> MetadataResult<()>;
+
+    /// Keys of all objects starting with `prefix`, in no particular order
+    async fn keys(&self, prefix: &str) -> MetadataResult<Vec<String>>;
+
+    /// Keys of objects with blocks of `block_ids`, read by data nodes that look up
+    /// their blocks. The objects can have lost the blocks since then.
+    async fn block_keys(&self, block_ids: &[Uuid]) -> MetadataResult<Vec<String>>;
+
+    /// Keys of objects with a multipart upload started before `started_before`, in
+    /// unix seconds. Other objects are not read.
+    async fn expired_uploads(&self, started_before: u64) -> MetadataResult<Vec<String>>;
+
+    /// Up to `limit` paths of objects of `owner` starting with `prefix` and greater
+    /// than `start_after`, in lexicographic order. Objects of other owners are not read.
+    async fn list(
+        &self,
+        owner: Uuid,
+        prefix: &str,
+        start_after: &str,
+        limit: usize,
+    ) -> MetadataResult<Vec<String>>;
+}
+
+/// Opens the backend chosen in `config`.
+pub async fn open(config: &Config) -> Box<dyn MetadataStore> {
+    match config.metadata_backend {
+        MetadataBackend::Redis => {
+            let connection = config
+                .database_connection
+                .clone()
+                .expect("The redis metadata backend needs a database connection");
+            Box::new(
+                RedisStore::open(redis::Client::open(connection).unwrap())
+                    .await
+                    .expect("Unable to open the redis metadata store"),
+            )
+        }
+        MetadataBackend::Embedded => Box::new(
+            EmbeddedStore::open(&config.metadata_path)
+                .await
+                .expect("Unable to open the embedded metadata store"),
+        ),
+        MetadataBackend::Memory => {
+            tracing::warn!("Metadata is kept in memory and will be lost on restart");
+            Box::new(MemoryStore::new())
+        }
+    }
+}
+
+/// Keys of objects by the ids of their blocks, all parts of a block belong to
+/// one object. Kept next to the objects by the in-process backends.
+#[derive(Default)]
+struct BlockIndex(HashMap<Uuid, String>);
+
+impl BlockIndex {
+    fn insert(&mut self, key: &str, object: &Object<String>) {
+        for block in object.get_all_blocks() {
+            self.0.insert(block.id, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, object: &Object<String>) {
+        for block in object.get_all_blocks() {
+            self.0.remove(&block.id);
+        }
+    }
+
+    fn find(&self, block_ids: &[Uuid]) -> Vec<String> {
+        block_ids
+            .iter()
+            .filter_map(|block_id| self.0.get(block_id).cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Keys of objects with an open multipart upload ordered by its start time. Kept
+/// next to the objects by the in-process backends.
+#[derive(Default)]
+struct UploadIndex(BTreeSet<(u64, String)>);
+
+impl UploadIndex {
+    /// Replaces the entry of the `old` object stored at `key` by the one of `new`.
+    fn update(&mut self, key: &str, old: Option<&Object<String>>, new: Option<&Object<String>>) {
+        if let Some(upload) = old.and_then(|object| object.upload.as_ref()) {
+            self.0.remove(&(upload.started_at, key.to_string()));
+        }
+        if let Some(upload) = new.and_then(|object| object.upload.as_ref()) {
+            self.0.insert((upload.started_at, key.to_string()));
+        }
+    }
+
+    fn started_before(&self, started_before: u64) -> Vec<String> {
+        self.0
+            .range(..(started_before, String::new()))
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+}
+
+/// Sorted entries of `objects` starting with `prefix` and greater than `start_after`.
+fn key_range<'a, V>(
+    objects: &'a BTreeMap<String, V>,
+    prefix: &'a str,
+    start_after: &str,
+) -> impl Iterator<Item = (&'a String, &'a V)> {
+    let start = if start_after >= prefix {
+        Bound::Excluded(start_after)
+    } else {
+        Bound::Included(prefix)
+    };
+
+    objects
+        .range::<str, _>((start, Bound::Unbounded))
+        .take_while(move |(key, _)| key.starts_with(prefix))
+}
+
+/// Paths of `owner` in `objects` kept under object keys, see [`MetadataStore::list`].
+fn list_owned<V>(
+    objects: &BTreeMap<String, V>,
+    owner: Uuid,
+    prefix: &str,
+    start_after: &str,
+    limit: usize,
+) -> Vec<String> {
+    let namespace = object_key(owner, "");
+    let prefix = object_key(owner, prefix);
+    let start_after = match start_after {
+        "" => String::new(),
+        path => object_key(owner, path),
+    };
+
+    key_range(objects, &prefix, &start_after)
+        .map(|(key, _)| key[namespace.len()..].to_string())
+        .take(limit)
+        .collect()
+}
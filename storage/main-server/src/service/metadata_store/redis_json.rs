@@ -0,0 +1,403 @@
+use super::MetadataStore;
+use crate::service::metadata_service::MetadataResult;
+use crate::storage_types::object::{parse_object_key, Object};
+use async_trait::async_trait;
+use redis::aio::Connection;
+use redis::{AsyncCommands, ErrorKind, JsonAsyncCommands, RedisError, RedisResult, Script};
+use shared::main_server_error::MetadataError;
+use uuid::Uuid;
+
+/// Version of the key layout, every key starts with it
+pub(crate) const LAYOUT: &str = "v1";
+/// Holds the layout version once the database uses it
+pub(crate) const LAYOUT_KEY: &str = "layout";
+/// Keys asked from Redis per SCAN call
+const SCAN_COUNT: usize = 1000;
+
+/// Stores the document `KEYS[1]`, adds its path `ARGV[2]` to the index `KEYS[2]`
+/// of its owner and maps its block ids `ARGV[5..]` to its key `ARGV[3]` in `KEYS[3]`.
+/// The key is kept in the uploads `KEYS[4]` by the start `ARGV[4]` of its multipart
+/// upload, an empty start removes it.
+const SET: &str = r#"
+redis.call('JSON.SET', KEYS[1], '$', ARGV[1])
+redis.call('ZADD', KEYS[2], 0, ARGV[2])
+for i = 5, #ARGV do
+    redis.call('HSET', KEYS[3], ARGV[i], ARGV[3])
+end
+if ARGV[4] == '' then
+    redis.call('ZREM', KEYS[4], ARGV[3])
+else
+    redis.call('ZADD', KEYS[4], ARGV[4], ARGV[3])
+end
+return 1
+"#;
+
+/// Replaces the document only if its version is still `ARGV[1]`. Documents stored
+/// before versions were introduced have no version field and are at the first one.
+/// Block ids `ARGV[5..]` and the upload `ARGV[4]` of the key `ARGV[3]` are indexed
+/// in `KEYS[2]` and `KEYS[3]` as by [`SET`].
+const COMPARE_AND_SET: &str = r#"
+local current = redis.call('JSON.GET', KEYS[1], '$.version')
+if not current then
+    return 0
+end
+local version = tonumber(string.match(current, '%d+')) or 1
+if version ~= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('JSON.SET', KEYS[1], '$', ARGV[2])
+for i = 5, #ARGV do
+    redis.call('HSET', KEYS[2], ARGV[i], ARGV[3])
+end
+if ARGV[4] == '' then
+    redis.call('ZREM', KEYS[3], ARGV[3])
+else
+    redis.call('ZADD', KEYS[3], ARGV[4], ARGV[3])
+end
+return 1
+"#;
+
+/// Removes the document `KEYS[1]`, its path `ARGV[1]` from the index `KEYS[2]`, its
+/// block ids `ARGV[3..]` from `KEYS[3]` and its key `ARGV[2]` from the uploads `KEYS[4]`.
+const DELETE: &str = r#"
+redis.call('ZREM', KEYS[2], ARGV[1])
+redis.call('ZREM', KEYS[4], ARGV[2])
+for i = 3, #ARGV do
+    redis.call('HDEL', KEYS[3], ARGV[i])
+end
+return redis.call('DEL', KEYS[1])
+"#;
+
+/// Objects kept as JSON documents in a RedisJSON instance. Keys are laid out as:
+///
+/// * `v1:object:<owner>:<path>` - document of the object
+/// * `v1:index:<owner>` - sorted set of paths of the owner, so listings are paged
+///   without reading objects of other owners
+/// * `v1:blocks` - hash of object keys by the ids of their blocks
+/// * `v1:uploads` - sorted set of keys of objects with a multipart upload, scored
+///   by its start
+/// * `layout` - version of the layout, see [`crate::migration`] for older layouts
+pub struct RedisStore {
+    client: redis::Client,
+    set: Script,
+    compare_and_set: Script,
+    delete: Script,
+}
+
+impl RedisStore {
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            set: Script::new(SET),
+            compare_and_set: Script::new(COMPARE_AND_SET),
+            delete: Script::new(DELETE),
+        }
+    }
+
+    /// Connects to Redis and checks that the database uses the current key layout.
+    pub async fn open(client: redis::Client) -> RedisResult<Self> {
+        let store = Self::new(client);
+        let mut connection = store.client.get_async_connection().await?;
+
+        let layout: Option<String> = connection.get(LAYOUT_KEY).await?;
+        match layout.as_deref() {
+            Some(LAYOUT) => (),
+            Some(layout) => {
+                return Err(layout_error(format!("Unknown key layout {}", layout)));
+            }
+            None if scan(&mut connection, "*", "ReJSON-RL").await?.is_empty() => {
+                let _: () = connection.set(LAYOUT_KEY, LAYOUT).await?;
+            }
+            None => {
+                return Err(layout_error(
+                    "Objects are stored in the old key layout, run `main-server migrate`"
+                        .to_string(),
+                ));
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Stores `object` under object key `key` and indexes it.
+    pub(crate) async fn put(
+        &self,
+        connection: &mut Connection,
+        key: &str,
+        object: &Object<String>,
+    ) -> RedisResult<()> {
+        self.set
+            .key(document_key(key))
+            .key(index_key(object.owner))
+            .key(blocks_key())
+            .key(uploads_key())
+            .arg(serde_json::to_string(object).unwrap())
+            .arg(object.name.as_str())
+            .arg(key)
+            .arg(upload_start(object))
+            .arg(block_ids(object))
+            .invoke_async(connection)
+            .await
+    }
+}
+
+fn block_ids(object: &Object<String>) -> Vec<String> {
+    object
+        .get_all_blocks()
+        .iter()
+        .map(|block| block.id.to_string())
+        .collect()
+}
+
+/// Start of the multipart upload of the object, empty if there is none.
+fn upload_start(object: &Object<String>) -> String {
+    object
+        .upload
+        .as_ref()
+        .map(|upload| upload.started_at.to_string())
+        .unwrap_or_default()
+}
+
+fn document_key(key: &str) -> String {
+    format!("{}:object:{}", LAYOUT, key)
+}
+
+fn index_key(owner: Uuid) -> String {
+    format!("{}:index:{}", LAYOUT, owner)
+}
+
+fn blocks_key() -> String {
+    format!("{}:blocks", LAYOUT)
+}
+
+fn uploads_key() -> String {
+    format!("{}:uploads", LAYOUT)
+}
+
+fn storage_error(err: RedisError) -> MetadataError {
+    MetadataError::StorageError(err.to_string())
+}
+
+fn layout_error(message: String) -> RedisError {
+    RedisError::from((ErrorKind::ClientError, "Wrong key layout", message))
+}
+
+/// Iterates over keys of `key_type` matching `pattern` with SCAN, which unlike KEYS
+/// does not block Redis.
+pub(crate) async fn scan(
+    connection: &mut Connection,
+    pattern: &str,
+    key_type: &str,
+) -> RedisResult<Vec<String>> {
+    let mut keys = vec![];
+    let mut cursor = 0u64;
+
+    loop {
+        let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .arg("TYPE")
+            .arg(key_type)
+            .query_async(connection)
+            .await?;
+        keys.extend(batch);
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    // A key can be returned more than once if the keyspace is resized during the scan
+    keys.sort_unstable();
+    keys.dedup();
+
+    Ok(keys)
+}
+
+/// Escapes glob characters of a key used in a MATCH pattern.
+fn escape_pattern(key: &str) -> String {
+    let mut pattern = String::with_capacity(key.len());
+    for char in key.chars() {
+        if matches!(char, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(char);
+    }
+
+    pattern
+}
+
+#[async_trait]
+impl MetadataStore for RedisStore {
+    async fn get(&self, key: &str) -> MetadataResult<Option<Object<String>>> {
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+        let object: Option<String> = connection
+            .json_get(document_key(key), ".")
+            .await
+            .map_err(storage_error)?;
+
+        object
+            .map(|object| serde_json::from_str(&object))
+            .transpose()
+            .map_err(|err| MetadataError::StorageError(format!("{} is damaged. {}", key, err)))
+    }
+
+    async fn set(&self, key: &str, object: &Object<String>) -> MetadataResult<()> {
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+
+        self.put(&mut connection, key, object)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: &str,
+        version: u64,
+        object: &Object<String>,
+    ) -> MetadataResult<bool> {
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+
+        self.compare_and_set
+            .key(document_key(key))
+            .key(blocks_key())
+            .key(uploads_key())
+            .arg(version)
+            .arg(serde_json::to_string(object).unwrap())
+            .arg(key)
+            .arg(upload_start(object))
+            .arg(block_ids(object))
+            .invoke_async(&mut connection)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn delete(&self, key: &str) -> MetadataResult<()> {
+        let Some((owner, path)) = parse_object_key(key) else {
+            return Ok(());
+        };
+        let block_ids = self.get(key).await.ok().flatten().as_ref().map(block_ids);
+
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+        let _: bool = self
+            .delete
+            .key(document_key(key))
+            .key(index_key(owner))
+            .key(blocks_key())
+            .key(uploads_key())
+            .arg(path)
+            .arg(key)
+            .arg(block_ids.unwrap_or_default())
+            .invoke_async(&mut connection)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    async fn keys(&self, prefix: &str) -> MetadataResult<Vec<String>> {
+        let namespace = document_key("");
+        let pattern = format!("{}{}*", namespace, escape_pattern(prefix));
+
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+        Ok(scan(&mut connection, &pattern, "ReJSON-RL")
+            .await
+            .map_err(storage_error)?
+            .into_iter()
+            .map(|key| key[namespace.len()..].to_string())
+            .collect())
+    }
+
+    async fn block_keys(&self, block_ids: &[Uuid]) -> MetadataResult<Vec<String>> {
+        if block_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let fields = block_ids
+            .iter()
+            .map(|block_id| block_id.to_string())
+            .collect::<Vec<_>>();
+
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+        let keys: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(blocks_key())
+            .arg(fields)
+            .query_async(&mut connection)
+            .await
+            .map_err(storage_error)?;
+        let mut keys = keys.into_iter().flatten().collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys.dedup();
+
+        Ok(keys)
+    }
+
+    async fn expired_uploads(&self, started_before: u64) -> MetadataResult<Vec<String>> {
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+        connection
+            .zrangebyscore(uploads_key(), "-inf", format!("({}", started_before))
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn list(
+        &self,
+        owner: Uuid,
+        prefix: &str,
+        start_after: &str,
+        limit: usize,
+    ) -> MetadataResult<Vec<String>> {
+        // Bounds of ZRANGEBYLEX, all keys starting with the prefix sort before `prefix\xff`
+        let min = if start_after >= prefix {
+            [b"(", start_after.as_bytes()].concat()
+        } else {
+            [b"[", prefix.as_bytes()].concat()
+        };
+        let max = if prefix.is_empty() {
+            b"+".to_vec()
+        } else {
+            [b"(", prefix.as_bytes(), b"\xff"].concat()
+        };
+
+        let mut connection = self
+            .client
+            .get_async_connection()
+            .await
+            .map_err(storage_error)?;
+        connection
+            .zrangebylex_limit(index_key(owner), min, max, 0, limit as isize)
+            .await
+            .map_err(storage_error)
+    }
+}
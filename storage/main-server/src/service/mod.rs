@@ -1,3 +1,6 @@
+pub mod garbage_collector;
+pub mod meta_service;
 pub mod metadata_controller;
 mod metadata_service;
-pub mod metadata_service_redis;
+pub mod metadata_store;
+pub mod replication_service;
@@ -0,0 +1,211 @@
+use super::meta_service::MetaService;
+use super::metadata_service::{MetadataResult, MetadataService};
+use crate::data_node_client::DataNodeClient;
+use crate::storage_types::commit_types::block::Block;
+use shared::hash::{Checksum, HashAlgorithm};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Restores the replication factor of blocks which lost a copy, either on a
+/// dead data node or because a scrubber found it corrupted.
+pub struct ReplicationService {
+    metadata_service: Arc<MetaService>,
+    data_node_client: Arc<DataNodeClient>,
+}
+
+impl ReplicationService {
+    pub fn new(metadata_service: Arc<MetaService>, data_node_client: Arc<DataNodeClient>) -> Self {
+        Self {
+            metadata_service,
+            data_node_client,
+        }
+    }
+
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            let dead_nodes = self.data_node_client.get_dead_nodes().await;
+            if !dead_nodes.is_empty() {
+                self.repair(&dead_nodes).await;
+            }
+        }
+    }
+
+    /// Takes the corrupted copy out of the metadata, restores the block from a
+    /// healthy copy and deletes the corrupted one.
+    pub async fn quarantine_block(
+        &self,
+        filename: &str,
+        block_id: Uuid,
+        part: usize,
+        endpoint: &str,
+    ) -> MetadataResult<()> {
+        let object = self.metadata_service.get_object(filename).await?;
+        let Some(block) = object
+            .get_all_blocks()
+            .iter()
+            .find(|block| block.id == block_id && block.part == part)
+        else {
+            return Ok(());
+        };
+
+        tracing::warn!(
+            "Block {}_{} of {} is corrupted on {}",
+            block_id,
+            part,
+            filename,
+            endpoint
+        );
+
+        let lost = HashSet::from([endpoint.to_string()]);
+        if self
+            .restore_block(
+                filename,
+                block,
+                self.metadata_service.get_block_size(&object),
+                object.hash_algorithm,
+                &lost,
+            )
+            .await
+        {
+            self.data_node_client
+                .delete_block(endpoint, block_id, part)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn repair(&self, dead_nodes: &HashSet<String>) {
+        let objects = match self.metadata_service.get_files("").await {
+            Ok(objects) => objects,
+            // Dead nodes stay dead, the repair is repeated on the next check
+            Err(err) => {
+                tracing::error!("Can't look for blocks of dead nodes. {}", err);
+                return;
+            }
+        };
+        for object in objects {
+            for block in object.get_all_blocks() {
+                self.restore_block(
+                    &object.key(),
+                    block,
+                    self.metadata_service.get_block_size(&object),
+                    object.hash_algorithm,
+                    dead_nodes,
+                )
+                .await;
+            }
+        }
+    }
+
+    /// Copies the block to healthy data nodes instead of its `lost` locations.
+    /// Returns `false` if some of them could not be replaced, they are kept in the
+    /// metadata so the next pass retries them.
+    async fn restore_block(
+        &self,
+        filename: &str,
+        block: &Block<String, Checksum>,
+        block_size: usize,
+        hash_algorithm: HashAlgorithm,
+        lost: &HashSet<String>,
+    ) -> bool {
+        let locations = std::iter::once(&block.dst)
+            .chain(block.replicas.iter())
+            .collect::<Vec<_>>();
+
+        if !locations.iter().any(|&dst| lost.contains(dst)) {
+            return true;
+        }
+
+        let survivors = locations
+            .iter()
+            .filter(|&&dst| !lost.contains(dst))
+            .map(|&dst| dst.clone())
+            .collect::<Vec<_>>();
+
+        let Some(source) = survivors.first() else {
+            tracing::error!(
+                "All copies of block {}_{} of {} are lost",
+                block.id,
+                block.part,
+                filename
+            );
+            return false;
+        };
+
+        let targets = self
+            .data_node_client
+            .pick_nodes(locations.len() - survivors.len(), &locations, block_size)
+            .await;
+
+        let mut replacements = vec![];
+        for target in targets {
+            match self
+                .data_node_client
+                .replicate_block(&target, source, block, hash_algorithm, block_size)
+                .await
+            {
+                Ok(_) => replacements.push(target),
+                Err(err) => tracing::error!("{}", err),
+            }
+        }
+
+        let replaced = replacements.len();
+        if replaced > 0 {
+            let mut replacements = replacements.into_iter();
+            let mut new_locations = locations
+                .iter()
+                .map(|&dst| {
+                    if lost.contains(dst) {
+                        replacements.next().unwrap_or_else(|| dst.clone())
+                    } else {
+                        dst.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .into_iter();
+
+            let result = self
+                .metadata_service
+                .relocate_block(
+                    filename,
+                    block.id,
+                    block.part,
+                    new_locations.next().unwrap(/*Never panic*/),
+                    new_locations.collect(),
+                )
+                .await;
+            if let Err(err) = result {
+                tracing::error!("{}", err);
+                return false;
+            }
+        }
+
+        let missing = locations.len() - survivors.len() - replaced;
+        if missing > 0 {
+            tracing::warn!(
+                "Block {}_{} of {} is still missing {} copies",
+                block.id,
+                block.part,
+                filename,
+                missing
+            );
+            return false;
+        }
+
+        tracing::info!(
+            "Block {}_{} of {} was re-replicated",
+            block.id,
+            block.part,
+            filename
+        );
+
+        true
+    }
+}
@@ -1,9 +1,12 @@
 use crate::constants::MAX_REPLICAS;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use shared::hash::Checksum;
+use smallvec::SmallVec;
 use std::fmt::Debug;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Dst: Deserialize<'de>, Hash: Deserialize<'de> + From<Checksum>"))]
 pub struct Block<Dst, Hash>
 where
     Dst: Serialize,
@@ -12,6 +15,49 @@ where
     pub(crate) id: Uuid,
     pub(crate) part: usize,
     pub(crate) dst: Dst,
-    pub(crate) replicas: Vec<[Dst; MAX_REPLICAS]>,
+    pub(crate) replicas: SmallVec<[Dst; MAX_REPLICAS]>,
+    #[serde(deserialize_with = "stored_checksum")]
     pub(crate) checksum: Hash,
 }
+
+/// Reads checksums stored before the hash algorithm was chosen per object, when
+/// they were CRC32 numbers. Zero marked a block whose checksum was not reported.
+pub(crate) fn stored_checksum<'de, D, Hash>(deserializer: D) -> Result<Hash, D::Error>
+where
+    D: Deserializer<'de>,
+    Hash: Deserialize<'de> + From<Checksum>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored<Hash> {
+        Crc32(u32),
+        Checksum(Hash),
+    }
+
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Crc32(0) => Checksum::new().into(),
+        Stored::Crc32(checksum) => checksum.to_le_bytes().to_vec().into(),
+        Stored::Checksum(checksum) => checksum,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(checksum: &str) -> Block<String, Checksum> {
+        let json = format!(
+            r#"{{"id":"{}","part":0,"dst":"node","replicas":[],"checksum":{}}}"#,
+            Uuid::nil(),
+            checksum
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_stored_checksum() {
+        assert_eq!(block("305419896").checksum, vec![0x78, 0x56, 0x34, 0x12]);
+        assert!(block("0").checksum.is_empty());
+        assert_eq!(block("[1,2,3]").checksum, vec![1, 2, 3]);
+    }
+}
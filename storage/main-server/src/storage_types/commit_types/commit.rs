@@ -1,6 +1,7 @@
 use super::sequence::Sequence;
 use crate::storage_types::commit_types::block::Block;
 use serde::{Deserialize, Serialize};
+use shared::hash::Checksum;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Commits<T>
@@ -14,15 +15,54 @@ impl<T> Commits<T>
 where
     T: Serialize,
 {
-    pub fn last(&self) -> &Block<T, u32> {
+    pub fn last(&self) -> &Block<T, Checksum> {
         match self {
             Commits::Sequence(seq) => seq.seq.last().unwrap(),
         }
     }
 
-    pub fn index(&self, index: usize) -> Option<&Block<T, u32>> {
+    /// Last commit with a checksum reported by its data node. Later commits are
+    /// still being written and must not be served to readers.
+    pub fn last_written(&self) -> Option<&Block<T, Checksum>> {
         match self {
-            Commits::Sequence(seq) => seq.seq.get(index),
+            Commits::Sequence(seq) => seq.seq.iter().rev().find(|block| is_written(block)),
         }
     }
+
+    /// Commit at `index` if it is written.
+    pub fn index(&self, index: usize) -> Option<&Block<T, Checksum>> {
+        match self {
+            Commits::Sequence(seq) => seq.seq.get(index).filter(|block| is_written(block)),
+        }
+    }
+}
+
+fn is_written<T: Serialize>(block: &Block<T, Checksum>) -> bool {
+    !block.checksum.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smallvec::SmallVec;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_unwritten_commit_is_not_served() {
+        let block = |checksum: Checksum| Block {
+            id: Uuid::new_v4(),
+            part: 0,
+            dst: String::new(),
+            replicas: SmallVec::new(),
+            checksum,
+        };
+        let written = block(vec![1]);
+        let commits = Commits::Sequence(Sequence {
+            seq: vec![written.clone(), block(vec![])],
+        });
+
+        assert_eq!(commits.last_written().unwrap().id, written.id);
+        assert!(commits.index(0).is_some());
+        assert!(commits.index(1).is_none());
+    }
 }
@@ -1,49 +1,57 @@
-use crate::storage_types::commit_types::block::Block;
+use crate::storage_types::commit_types::block::{stored_checksum, Block};
 use serde::{Deserialize, Serialize};
+use shared::hash::{Checksum, HashAlgorithm};
 use std::fmt::Debug;
 use uuid::Uuid;
-use zerocopy::AsBytes;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Hash: Deserialize<'de> + From<Checksum>"))]
 struct Node<Hash>
 where
-    Hash: Serialize + AsBytes + Copy,
+    Hash: Serialize + Clone,
 {
     left: usize,
     right: usize,
+    #[serde(deserialize_with = "stored_checksum")]
     pub(crate) checksum: Hash,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Dst: Deserialize<'de>, Hash: Deserialize<'de> + From<Checksum>"))]
 pub struct MerkleTree<Dst, Hash>
 where
     Dst: Serialize + Debug,
-    Hash: Serialize + Copy + AsBytes + Debug,
+    Hash: Serialize + Clone + Debug,
 {
     leaves: Vec<Block<Dst, Hash>>,
     nodes: Vec<Vec<Node<Hash>>>,
 }
 
-impl<Dst> MerkleTree<Dst, u32>
+impl<Dst> MerkleTree<Dst, Checksum>
 where
     Dst: Serialize + Debug,
 {
-    pub fn build(blocks: Vec<Block<Dst, u32>>) -> Self {
+    pub fn build(blocks: Vec<Block<Dst, Checksum>>, algorithm: HashAlgorithm) -> Self {
         let mut tree = Self {
             leaves: blocks,
             nodes: vec![],
         };
-        tree.build_tree();
+        tree.build_tree(algorithm);
 
         tree
     }
 
-    #[allow(dead_code)]
-    pub fn root(&self) -> u32 {
-        self.nodes.last().unwrap()[0].checksum
+    pub fn root(&self) -> &Checksum {
+        &self.nodes.last().unwrap()[0].checksum
     }
 
-    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: u32) {
+    pub fn update_block(
+        &mut self,
+        block_id: Uuid,
+        part: usize,
+        checksum: Checksum,
+        algorithm: HashAlgorithm,
+    ) {
         for block in self.leaves.iter_mut() {
             if block.id == block_id && block.part == part {
                 block.checksum = checksum;
@@ -51,14 +59,19 @@ where
             }
         }
 
-        self.build_tree();
+        self.build_tree(algorithm);
     }
 
-    pub fn leaves(&self) -> &[Block<Dst, u32>] {
+    pub fn leaves(&self) -> &[Block<Dst, Checksum>] {
         &self.leaves
     }
 
-    fn build_tree(&mut self) {
+    /// Checksums must be changed through `update_block` to keep the tree consistent.
+    pub fn leaves_mut(&mut self) -> &mut [Block<Dst, Checksum>] {
+        &mut self.leaves
+    }
+
+    fn build_tree(&mut self, algorithm: HashAlgorithm) {
         let mut offset = 0;
         let mut nodes = vec![];
 
@@ -69,13 +82,7 @@ where
 
             for (i, chunk) in chunks.enumerate() {
                 if chunk.len() == 2 {
-                    let checksum = {
-                        let vec = [chunk[0].checksum, chunk[1].checksum];
-                        let bytes = vec.as_bytes();
-
-                        //TODO: Ломает всю абстракцию выбора хеша
-                        crc32fast::hash(bytes)
-                    };
+                    let checksum = algorithm.combine(&chunk[0].checksum, &chunk[1].checksum);
 
                     inner_nodes.push(Node {
                         left: offset + i * 2,
@@ -86,7 +93,7 @@ where
                     inner_nodes.push(Node {
                         left: offset + i * 2,
                         right: offset + i * 2,
-                        checksum: chunk[0].checksum,
+                        checksum: chunk[0].checksum.clone(),
                     });
                 }
             }
@@ -102,13 +109,7 @@ where
 
                 for (i, chunk) in chunks.enumerate() {
                     if chunk.len() == 2 {
-                        let checksum = {
-                            let vec = [chunk[0].checksum, chunk[1].checksum];
-                            let bytes = vec.as_bytes();
-
-                            //TODO: Ломает всю абстракцию выбора хеша
-                            crc32fast::hash(bytes)
-                        };
+                        let checksum = algorithm.combine(&chunk[0].checksum, &chunk[1].checksum);
 
                         inner_nodes.push(Node {
                             left: offset + i * 2,
@@ -119,7 +120,7 @@ where
                         inner_nodes.push(Node {
                             left: offset + i * 2,
                             right: offset + i * 2,
-                            checksum: chunk[0].checksum,
+                            checksum: chunk[0].checksum.clone(),
                         });
                     }
                 }
@@ -1,5 +1,6 @@
 use super::block::Block;
 use serde::{Deserialize, Serialize};
+use shared::hash::Checksum;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -7,14 +8,14 @@ pub struct Sequence<T>
 where
     T: Serialize,
 {
-    pub(crate) seq: Vec<Block<T, u32>>,
+    pub(crate) seq: Vec<Block<T, Checksum>>,
 }
 
 impl<T> Sequence<T>
 where
     T: Serialize,
 {
-    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: u32) {
+    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: Checksum) {
         for block in self.seq.iter_mut() {
             if block.id == block_id && block.part == part {
                 block.checksum = checksum;
@@ -1,6 +1,7 @@
 use super::commit_types::block::Block;
 use super::commit_types::merkle_tree::MerkleTree;
 use serde::{Deserialize, Serialize};
+use shared::hash::{Checksum, HashAlgorithm};
 use std::fmt::Debug;
 use uuid::Uuid;
 
@@ -9,18 +10,32 @@ pub struct LargeFile<T>
 where
     T: Serialize + Debug,
 {
-    pub(crate) tree: MerkleTree<T, u32>,
+    pub(crate) tree: MerkleTree<T, Checksum>,
+    /// Size of every block but the last one. Files stored before it was recorded
+    /// are at zero and use the block size of the main server.
+    #[serde(default)]
+    pub(crate) block_size: usize,
 }
 
 impl<T> LargeFile<T>
 where
     T: Serialize + Debug,
 {
-    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: u32) {
-        self.tree.update_block(block_id, part, checksum);
+    pub fn update_block(
+        &mut self,
+        block_id: Uuid,
+        part: usize,
+        checksum: Checksum,
+        algorithm: HashAlgorithm,
+    ) {
+        self.tree.update_block(block_id, part, checksum, algorithm);
     }
 
-    pub fn get_all_blocks(&self) -> &[Block<T, u32>] {
+    pub fn get_all_blocks(&self) -> &[Block<T, Checksum>] {
         self.tree.leaves()
     }
+
+    pub fn get_all_blocks_mut(&mut self) -> &mut [Block<T, Checksum>] {
+        self.tree.leaves_mut()
+    }
 }
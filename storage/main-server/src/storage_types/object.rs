@@ -1,9 +1,12 @@
 use super::large_file::LargeFile;
 use super::small_file::SmallFile;
+use crate::constants::MAX_GROUP_ACCESS;
 use crate::storage_types::commit_types::block::Block;
 use fast_str::FastStr;
 use serde::{Deserialize, Serialize};
+use shared::hash::{Checksum, HashAlgorithm};
 use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -19,10 +22,16 @@ impl<T> ObjectVariant<T>
 where
     T: Serialize + Debug,
 {
-    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: u32) {
+    pub fn update_block(
+        &mut self,
+        block_id: Uuid,
+        part: usize,
+        checksum: Checksum,
+        algorithm: HashAlgorithm,
+    ) {
         match self {
             ObjectVariant::LargeFile(file) => {
-                file.update_block(block_id, part, checksum);
+                file.update_block(block_id, part, checksum, algorithm);
             }
             ObjectVariant::SmallFile(file) => {
                 file.update_block(block_id, part, checksum);
@@ -31,6 +40,60 @@ where
     }
 }
 
+/// Lifecycle of an object. Only committed objects are visible to readers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ObjectState {
+    /// Blocks are allocated, but not all of them are written yet
+    Pending,
+    /// Objects stored before the state was introduced are committed
+    #[default]
+    Committed,
+    /// Blocks are being removed from data nodes
+    Deleting,
+}
+
+/// Operations on an object a group can be allowed to perform. The owner can always
+/// perform all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub delete: bool,
+}
+
+impl Permissions {
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Read => self.read,
+            Permission::Write => self.write,
+            Permission::Delete => self.delete,
+        }
+    }
+}
+
+/// Access of the members of a group to an object.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupAccess {
+    pub(crate) group_id: Uuid,
+    pub(crate) permissions: Permissions,
+}
+
+/// Multipart upload session of a large file. The object stays invisible to readers
+/// until the session is completed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub(crate) id: Uuid,
+    /// Unix time in seconds
+    pub(crate) started_at: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Object<T>
 where
@@ -39,30 +102,149 @@ where
     pub(crate) name: FastStr,
     pub(crate) size: usize,
     pub(crate) owner: Uuid,
+    #[serde(default)]
+    pub(crate) hash_algorithm: HashAlgorithm,
     pub(crate) inner: ObjectVariant<T>,
+    #[serde(default)]
+    pub(crate) state: ObjectState,
+    #[serde(default)]
+    pub(crate) upload: Option<MultipartUpload>,
+    /// Incremented on every change, so clients can update the object conditionally.
+    /// Objects stored before versions were introduced are at the first version.
+    #[serde(default = "first_version")]
+    pub(crate) version: u64,
+    /// Groups the object is shared with, at most `MAX_GROUP_ACCESS`
+    #[serde(default)]
+    pub(crate) groups: Vec<GroupAccess>,
+    /// Unix time in seconds. Objects stored before it was recorded are at 0.
+    #[serde(default)]
+    pub(crate) created_at: u64,
+}
+
+fn first_version() -> u64 {
+    1
+}
+
+/// Key of the object at `path` of `owner` in `<owner>:<path>` format. Data nodes
+/// report written blocks by this key.
+pub fn object_key(owner: Uuid, path: &str) -> String {
+    format!("{}:{}", owner, path)
+}
+
+/// Parses object key in `<owner>:<path>` format.
+pub fn parse_object_key(key: &str) -> Option<(Uuid, &str)> {
+    let (owner, path) = key.split_once(':')?;
+
+    Some((owner.parse().ok()?, path))
 }
 
 impl<T> Object<T>
 where
     T: Serialize + Debug,
 {
-    pub fn new(name: FastStr, size: usize, owner: Uuid, inner: ObjectVariant<T>) -> Self {
+    pub fn new(
+        name: FastStr,
+        size: usize,
+        owner: Uuid,
+        hash_algorithm: HashAlgorithm,
+        inner: ObjectVariant<T>,
+    ) -> Self {
         Self {
             name,
             size,
             owner,
+            hash_algorithm,
             inner,
+            state: ObjectState::Pending,
+            upload: None,
+            version: first_version(),
+            groups: vec![],
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: Checksum) {
+        self.inner
+            .update_block(block_id, part, checksum, self.hash_algorithm);
+    }
+
+    /// Key the object is stored under, unique across owners.
+    pub fn key(&self) -> String {
+        object_key(self.owner, &self.name)
+    }
+
+    /// Whether `user_id`, a member of `group_ids`, may perform `permission`.
+    pub fn allows(&self, user_id: Uuid, group_ids: &[Uuid], permission: Permission) -> bool {
+        self.owner == user_id
+            || self.groups.iter().any(|access| {
+                group_ids.contains(&access.group_id) && access.permissions.allows(permission)
+            })
+    }
+
+    /// Sets the permissions of `group_id`, the group is removed if it has none.
+    /// Returns `false` if the object is already shared with too many groups.
+    pub fn set_group_access(&mut self, group_id: Uuid, permissions: Permissions) -> bool {
+        let position = self
+            .groups
+            .iter()
+            .position(|access| access.group_id == group_id);
+
+        match position {
+            _ if permissions == Permissions::default() => {
+                self.groups.retain(|access| access.group_id != group_id);
+            }
+            Some(position) => self.groups[position].permissions = permissions,
+            None if self.groups.len() == MAX_GROUP_ACCESS => return false,
+            None => self.groups.push(GroupAccess {
+                group_id,
+                permissions,
+            }),
         }
+
+        true
     }
 
-    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: u32) {
-        self.inner.update_block(block_id, part, checksum);
+    pub fn is_committed(&self) -> bool {
+        self.state == ObjectState::Committed
+    }
+
+    /// Commits a pending object once every block has a checksum. Objects of
+    /// unfinished multipart uploads are committed only by completing the upload.
+    pub fn commit_if_written(&mut self) {
+        if self.state == ObjectState::Pending
+            && self.upload.is_none()
+            && self
+                .get_all_blocks()
+                .iter()
+                .all(|block| !block.checksum.is_empty())
+        {
+            self.state = ObjectState::Committed;
+        }
     }
 
-    pub fn get_all_blocks(&self) -> &[Block<T, u32>] {
+    /// Whether the object stayed pending for longer than `max_age`, so the client
+    /// which created it is not going to write it anymore. Multipart uploads are
+    /// expired by their own age.
+    pub fn is_abandoned(&self, now: u64, max_age: Duration) -> bool {
+        self.state == ObjectState::Pending
+            && self.upload.is_none()
+            && now.saturating_sub(self.created_at) >= max_age.as_secs()
+    }
+
+    pub fn get_all_blocks(&self) -> &[Block<T, Checksum>] {
         return match self.inner {
             ObjectVariant::LargeFile(ref file) => file.get_all_blocks(),
             ObjectVariant::SmallFile(ref file) => file.get_all_blocks(),
         };
     }
+
+    pub fn get_all_blocks_mut(&mut self) -> &mut [Block<T, Checksum>] {
+        match self.inner {
+            ObjectVariant::LargeFile(ref mut file) => file.get_all_blocks_mut(),
+            ObjectVariant::SmallFile(ref mut file) => file.get_all_blocks_mut(),
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use super::commit_types::commit::Commits;
 use crate::storage_types::commit_types::block::Block;
 use serde::{Deserialize, Serialize};
+use shared::hash::Checksum;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -15,7 +16,7 @@ impl<T> SmallFile<T>
 where
     T: Serialize,
 {
-    pub fn add_block(&mut self, block: Block<T, u32>) {
+    pub fn add_block(&mut self, block: Block<T, Checksum>) {
         match self.commits {
             Commits::Sequence(ref mut seq) => {
                 seq.seq.push(block);
@@ -23,7 +24,7 @@ where
         }
     }
 
-    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: u32) {
+    pub fn update_block(&mut self, block_id: Uuid, part: usize, checksum: Checksum) {
         match self.commits {
             Commits::Sequence(ref mut seq) => {
                 seq.update_block(block_id, part, checksum);
@@ -31,9 +32,15 @@ where
         }
     }
 
-    pub fn get_all_blocks(&self) -> &[Block<T, u32>] {
+    pub fn get_all_blocks(&self) -> &[Block<T, Checksum>] {
         match self.commits {
             Commits::Sequence(ref seq) => &seq.seq,
         }
     }
+
+    pub fn get_all_blocks_mut(&mut self) -> &mut [Block<T, Checksum>] {
+        match self.commits {
+            Commits::Sequence(ref mut seq) => &mut seq.seq,
+        }
+    }
 }
@@ -13,6 +13,12 @@ pub enum DataNodeError {
     DeleteBlockError(String),
     NoSpace,
     BlockOverflow(usize, usize),
+    ReplicateBlockError(String),
+    ChecksumMismatch(String),
+    UnknownHashAlgorithm(i32),
+    UnknownBlockSize(usize),
+    ReportChecksumError(String),
+    RechunkBlockError(String),
 }
 
 impl_converter!(DataNodeError);
@@ -51,6 +57,24 @@ impl std::fmt::Display for DataNodeError {
                     buffer_size, block_size
                 )
             }
+            DataNodeError::ReplicateBlockError(str) => {
+                write!(f, "Fail to replicate block {0}", str)
+            }
+            DataNodeError::ChecksumMismatch(str) => {
+                write!(f, "Checksum mismatch for {0}", str)
+            }
+            DataNodeError::UnknownHashAlgorithm(value) => {
+                write!(f, "Unknown hash algorithm {0}", value)
+            }
+            DataNodeError::UnknownBlockSize(block_size) => {
+                write!(f, "No pool of blocks with {0} bytes size", block_size)
+            }
+            DataNodeError::ReportChecksumError(str) => {
+                write!(f, "Fail to report checksum of block {0}", str)
+            }
+            DataNodeError::RechunkBlockError(str) => {
+                write!(f, "Fail to re-chunk block {0}", str)
+            }
         }
     }
 }
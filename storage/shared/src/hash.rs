@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::str::FromStr;
+
+/// Raw digest of a block or of a Merkle tree node. Empty until the block is written.
+pub type Checksum = Vec<u8>;
+
+/// Hash algorithm of block checksums and the Merkle tree of an object.
+/// Discriminants match the `HashAlgorithm` enums in the proto files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Crc32 = 0,
+    Sha256 = 1,
+    Blake3 = 2,
+}
+
+pub trait Hasher: Send {
+    fn update(&mut self, data: &[u8]);
+
+    fn finalize(self: Box<Self>) -> Checksum;
+}
+
+struct Crc32(crc32fast::Hasher);
+
+impl Hasher for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Checksum {
+        self.0.finalize().to_le_bytes().to_vec()
+    }
+}
+
+struct Sha256(sha2::Sha256);
+
+impl Hasher for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Checksum {
+        self.0.finalize().to_vec()
+    }
+}
+
+struct Blake3(blake3::Hasher);
+
+impl Hasher for Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Checksum {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+impl HashAlgorithm {
+    pub fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            HashAlgorithm::Crc32 => Box::new(Crc32(crc32fast::Hasher::new())),
+            HashAlgorithm::Sha256 => Box::new(Sha256(sha2::Sha256::new())),
+            HashAlgorithm::Blake3 => Box::new(Blake3(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn hash(self, data: &[u8]) -> Checksum {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Checksum of an inner Merkle tree node built from its two children.
+    pub fn combine(self, left: &[u8], right: &[u8]) -> Checksum {
+        let mut hasher = self.hasher();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize()
+    }
+}
+
+impl TryFrom<i32> for HashAlgorithm {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(HashAlgorithm::Crc32),
+            1 => Ok(HashAlgorithm::Sha256),
+            2 => Ok(HashAlgorithm::Blake3),
+            value => Err(value),
+        }
+    }
+}
+
+impl From<HashAlgorithm> for i32 {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        algorithm as i32
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crc32" => Ok(HashAlgorithm::Crc32),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            s => Err(format!("Unknown hash algorithm {}", s)),
+        }
+    }
+}
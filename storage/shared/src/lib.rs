@@ -1,4 +1,5 @@
 pub mod data_node_error;
 mod error;
+pub mod hash;
 pub mod main_server_error;
 pub mod register_client_error;
@@ -14,6 +14,17 @@ pub enum MetadataError {
     NoPermission(String),
     WrongSmallFileVersion(String),
     WrongSmallFileSize(usize, usize),
+    DataNodeNotFound(String),
+    DeleteBlockError(String),
+    NotEnoughDataNodes(usize, usize),
+    ReplicateBlockError(String),
+    UnknownHashAlgorithm(i32),
+    UploadNotFound(String),
+    IncompleteUpload(String),
+    VersionConflict(String),
+    TooManyGroups(usize),
+    StorageError(String),
+    RechunkBlockError(String),
 }
 
 impl Display for MetadataError {
@@ -53,6 +64,43 @@ impl Display for MetadataError {
                     src, constraint
                 )
             }
+            MetadataError::DataNodeNotFound(msg) => {
+                write!(f, "Data node {0} is not registered", msg)
+            }
+            MetadataError::DeleteBlockError(msg) => {
+                write!(f, "Fail to delete block. {0}", msg)
+            }
+            MetadataError::NotEnoughDataNodes(got, required) => {
+                write!(
+                    f,
+                    "Only {0} data nodes are connected, but {1} replicas are required",
+                    got, required
+                )
+            }
+            MetadataError::ReplicateBlockError(msg) => {
+                write!(f, "Fail to replicate block. {0}", msg)
+            }
+            MetadataError::UnknownHashAlgorithm(value) => {
+                write!(f, "Unknown hash algorithm {0}", value)
+            }
+            MetadataError::UploadNotFound(msg) => {
+                write!(f, "Multipart upload not found. {0}", msg)
+            }
+            MetadataError::IncompleteUpload(msg) => {
+                write!(f, "Can not complete multipart upload. {0}", msg)
+            }
+            MetadataError::VersionConflict(msg) => {
+                write!(f, "Object was changed concurrently. {0}", msg)
+            }
+            MetadataError::TooManyGroups(max) => {
+                write!(f, "Object can be shared with at most {0} groups", max)
+            }
+            MetadataError::StorageError(msg) => {
+                write!(f, "Metadata storage failed. {0}", msg)
+            }
+            MetadataError::RechunkBlockError(msg) => {
+                write!(f, "Fail to re-chunk block. {0}", msg)
+            }
         }
     }
 }
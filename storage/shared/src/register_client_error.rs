@@ -5,6 +5,8 @@ use std::fmt::Formatter;
 #[derive(Debug, Serialize, Deserialize)]
 pub enum RegistryError {
     WrongBlockSize(usize, usize, usize),
+    DataNodeUnreachable(String),
+    UnknownDataNode(String),
 }
 
 impl_converter!(RegistryError);
@@ -19,6 +21,12 @@ impl std::fmt::Display for RegistryError {
                     got, small, large
                 )
             }
+            RegistryError::DataNodeUnreachable(address) => {
+                write!(f, "Can not connect to data node {}", address)
+            }
+            RegistryError::UnknownDataNode(address) => {
+                write!(f, "Data node {} is not registered", address)
+            }
         }
     }
 }
@@ -15,12 +15,12 @@ async fn one_hundred_big_file_processing() {
         let user = Uuid::new_v4();
         let filename = format!("{}", user);
         client
-            .create_large_file(&filename, file)
+            .create_large_file(&filename, &[], file)
             .await
             .unwrap();
 
-        client.read_large_file(&filename).await.unwrap();
-        client.delete_file(&filename).await.unwrap();
+        client.read_large_file(None, &filename).await.unwrap();
+        client.delete_file(None, &filename).await.unwrap();
     });
     futures::future::join_all(jobs).await;
 }
@@ -31,9 +31,9 @@ fn bench(c: &mut Criterion) {
 
     group.bench_function("one_hundred_big_file_processing", |b| {
         b.to_async(tokio::runtime::Runtime::new().unwrap())
-            .iter(|| one_hundred_big_file_processing());
+            .iter(one_hundred_big_file_processing);
     });
 }
 
-criterion_group!(benches, from_elem);
+criterion_group!(benches, bench);
 criterion_main!(benches);
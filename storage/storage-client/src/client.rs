@@ -2,12 +2,22 @@ use crate::client::proto_data_node_api::data_node_service_api_client::DataNodeSe
 use crate::client::proto_data_node_api::{Range, ReadBlockRequest, UpdateBlockRequest};
 use crate::client::proto_main_server_api::main_server_service_api_client::MainServerServiceApiClient;
 use crate::client::proto_main_server_api::{
-    AddCommitSmallFileRequest, CreateFileRequest, DeleteFileRequest, FileRequest,
-    GetLargeFileRequest, GetSmallFileLastVersionRequest, GetSmallFileRequest,
+    AddCommitSmallFileRequest, BlockInfo, CompleteMultipartUploadRequest, CreateFileRequest,
+    DeleteFileRequest, FileRequest, GetLargeFileRequest, GetSmallFileLastVersionRequest,
+    GetSmallFileRequest, GrantAccessRequest, GroupPermissions, MultipartUploadRequest,
+    PartChecksum, RevokeAccessRequest, UploadPartRequest,
 };
 use crate::config::Config;
-use futures::StreamExt;
-use tokio::io::AsyncReadExt;
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use shared::hash::{Checksum, HashAlgorithm};
+use shared::main_server_error::MetadataError;
+use std::collections::HashMap;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::RwLock;
+use tokio_util::io::StreamReader;
+use tonic::transport::Channel;
+use uuid::Uuid;
 
 mod proto_data_node_api {
     tonic::include_proto!("data_node_api");
@@ -23,18 +33,46 @@ pub enum ObjectType {
     LargeFile,
 }
 
+/// Page of a file listing.
+#[derive(Debug)]
+pub struct FileListing {
+    pub files: Vec<(String, ObjectType)>,
+    /// Names up to and including the delimiter, one for all files under them
+    pub prefixes: Vec<String>,
+    /// Token of the next page, `None` on the last page
+    pub continuation_token: Option<String>,
+}
+
+/// What members of a group may do with a shared file.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub delete: bool,
+}
+
+/// Client of the storage. Files are named within the namespace of their owner,
+/// the `owner` of a request is `None` for files of the client itself and the id
+/// of another user for files that user shared with a group of the client.
 pub struct StorageClient {
     config: Config,
+    /// Connections to data nodes keyed by endpoint, shared by concurrent transfers.
+    data_nodes: RwLock<HashMap<String, DataNodeServiceApiClient<Channel>>>,
 }
 
 impl StorageClient {
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self {
+            config,
+            data_nodes: RwLock::new(HashMap::new()),
+        }
     }
 
+    /// Creates a small file, members of `share_with` groups may read it.
     pub async fn create_small_file(
         &self,
         filename: &str,
+        share_with: &[Uuid],
         mut file: tokio::fs::File,
     ) -> Result<(), StorageClientError> {
         let mut main_server_client = MainServerServiceApiClient::connect(format!(
@@ -50,8 +88,10 @@ impl StorageClient {
             .create_small_file(CreateFileRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
+                group_ids: self.group_ids(),
                 size: file_size,
+                hash_algorithm: self.config.hash_algorithm.into(),
+                share_with_group_ids: group_ids(share_with),
             })
             .await
             .map_err(|_| StorageClientError::CreateFileError)?
@@ -59,34 +99,13 @@ impl StorageClient {
 
         let block = remote_file.block.unwrap();
 
-        let mut data_node_client =
-            DataNodeServiceApiClient::connect(format!("http://{}", block.endpoint))
-                .await
-                .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
-
         //TODO: Error handle
         let mut buffer = vec![];
         file.read_to_end(&mut buffer).await.unwrap();
 
-        let update_info = UpdateBlockRequest {
-            filename: filename.to_string(),
-            block_id: block.block_id.clone(),
-            part: block.part,
-            range: Some(Range {
-                start: 0,
-                end: buffer.len() as u64,
-            }),
-            data: buffer,
-            hash: 0,
-        };
-
-        let stream = tokio_stream::iter(std::iter::once(update_info));
+        let update_info = self.update_requests(&block, &buffer)?;
 
-        let _ = data_node_client
-            .update_block(stream)
-            .await
-            .map_err(|_| StorageClientError::UpdateBlockError)?
-            .into_inner();
+        self.write_block(&block, update_info).await?;
 
         Ok(())
     }
@@ -94,8 +113,101 @@ impl StorageClient {
     pub async fn create_large_file(
         &self,
         filename: &str,
-        mut file: tokio::fs::File,
+        share_with: &[Uuid],
+        file: tokio::fs::File,
     ) -> Result<(), StorageClientError> {
+        let file_size = file.metadata().await.unwrap().len();
+
+        self.upload_large_file(filename, share_with, file, file_size)
+            .await
+    }
+
+    /// Uploads `size` bytes of `reader` as a large file in a multipart upload.
+    /// Up to `max_transfers` blocks are kept in memory and sent to the data nodes
+    /// concurrently in `read_buffer` sized messages. The upload is aborted if any
+    /// part fails. Members of `share_with` groups may read the file.
+    pub async fn upload_large_file<R>(
+        &self,
+        filename: &str,
+        share_with: &[Uuid],
+        reader: R,
+        size: u64,
+    ) -> Result<(), StorageClientError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let (upload_id, mut blocks) = self
+            .start_multipart_upload(filename, share_with, size)
+            .await?;
+        blocks.sort_by_key(|block| block.part);
+
+        let block_size = self.config.block_size as u64;
+        let parts = futures::stream::try_unfold(
+            (reader, blocks.into_iter()),
+            move |(mut reader, mut blocks)| async move {
+                let Some(block) = blocks.next() else {
+                    return Ok(None);
+                };
+
+                let mut buffer = vec![];
+                (&mut reader)
+                    .take(block_size)
+                    .read_to_end(&mut buffer)
+                    .await
+                    .map_err(|_| StorageClientError::ReadInputError)?;
+
+                let update_info = self.update_requests(&block, &buffer)?;
+                Ok(Some(((block, update_info), (reader, blocks))))
+            },
+        )
+        .map_ok(|(block, update_info)| async move {
+            let checksum = self.write_block(&block, update_info).await?;
+            Ok((block.part as usize, checksum))
+        })
+        .try_buffer_unordered(self.config.max_transfers.max(1))
+        .try_collect::<Vec<_>>()
+        .await;
+
+        let result = match parts {
+            Ok(parts) => {
+                self.complete_multipart_upload(None, filename, upload_id, &parts)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+
+        if result.is_err() {
+            let _ = self.abort_multipart_upload(None, filename, upload_id).await;
+        }
+
+        result
+    }
+
+    /// Starts a multipart upload of a large file of `size` bytes. The file is not
+    /// visible until `complete_multipart_upload` succeeds, then members of
+    /// `share_with` groups may read it.
+    pub async fn initiate_multipart_upload(
+        &self,
+        filename: &str,
+        share_with: &[Uuid],
+        size: u64,
+    ) -> Result<Uuid, StorageClientError> {
+        let (upload_id, _) = self
+            .start_multipart_upload(filename, share_with, size)
+            .await?;
+
+        Ok(upload_id)
+    }
+
+    /// Writes `data` as the given part of a multipart upload and returns its checksum.
+    pub async fn upload_part(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+        upload_id: Uuid,
+        part: usize,
+        data: &[u8],
+    ) -> Result<Checksum, StorageClientError> {
         let mut main_server_client = MainServerServiceApiClient::connect(format!(
             "http://{}",
             self.config.main_server_address
@@ -103,68 +215,126 @@ impl StorageClient {
         .await
         .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
 
-        let file_size = file.metadata().await.unwrap().len();
-
-        let remote_file = main_server_client
-            .create_large_file(CreateFileRequest {
+        let block = main_server_client
+            .upload_part(UploadPartRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
-                size: file_size,
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+                upload_id: upload_id.as_bytes().to_vec(),
+                part: part as u64,
             })
             .await
-            .map_err(|_| StorageClientError::CreateFileError)?
+            .map_err(|_| StorageClientError::UploadPartError)?
             .into_inner();
 
-        //TODO: Error handle
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer).await.unwrap();
+        let update_info = self.update_requests(&block, data)?;
+
+        self.write_block(&block, update_info).await
+    }
+
+    /// Returns parts of a multipart upload whose checksums are already recorded by
+    /// the main server, so an interrupted upload can send only the missing ones.
+    pub async fn list_uploaded_parts(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+        upload_id: Uuid,
+    ) -> Result<Vec<(usize, Checksum)>, StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
 
-        let chunks = buffer.chunks(self.config.block_size);
-
-        assert_eq!(chunks.len(), remote_file.blocks.len());
-
-        let chunks = chunks
-            .zip(remote_file.blocks)
-            .map(move |(data, block)| {
-                (
-                    UpdateBlockRequest {
-                        filename: filename.to_string(),
-                        block_id: block.block_id.clone(),
-                        part: block.part,
-                        data: data.to_vec(),
-                        range: Some(Range {
-                            start: 0,
-                            end: data.len() as u64,
-                        }),
-                        hash: 0,
-                    },
-                    block.endpoint,
-                )
+        let parts = main_server_client
+            .list_uploaded_parts(MultipartUploadRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+                upload_id: upload_id.as_bytes().to_vec(),
             })
-            .collect::<Vec<(UpdateBlockRequest, String)>>();
+            .await
+            .map_err(|_| StorageClientError::ListUploadedPartsError)?
+            .into_inner()
+            .parts;
 
-        //TODO: Join all
-        for (block, endpoint) in chunks {
-            let mut data_node_client =
-                DataNodeServiceApiClient::connect(format!("http://{}", endpoint))
-                    .await
-                    .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
+        Ok(parts
+            .into_iter()
+            .map(|block| (block.part as usize, block.checksum))
+            .collect())
+    }
 
-            let stream = tokio_stream::iter(std::iter::once(block));
+    /// Publishes the file. Fails if a part is missing or its checksum differs from
+    /// the one written by the data nodes.
+    pub async fn complete_multipart_upload(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+        upload_id: Uuid,
+        parts: &[(usize, Checksum)],
+    ) -> Result<(), StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
 
-            let _ = data_node_client
-                .update_block(stream)
-                .await
-                .map_err(|_| StorageClientError::UpdateBlockError)?
-                .into_inner();
-        }
+        let _ = main_server_client
+            .complete_multipart_upload(CompleteMultipartUploadRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+                upload_id: upload_id.as_bytes().to_vec(),
+                parts: parts
+                    .iter()
+                    .map(|(part, checksum)| PartChecksum {
+                        part: *part as u64,
+                        checksum: checksum.clone(),
+                    })
+                    .collect(),
+            })
+            .await
+            .map_err(|_| StorageClientError::CompleteUploadError)?;
+
+        Ok(())
+    }
+
+    /// Drops the multipart upload together with the already written blocks.
+    pub async fn abort_multipart_upload(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+        upload_id: Uuid,
+    ) -> Result<(), StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
+
+        let _ = main_server_client
+            .abort_multipart_upload(MultipartUploadRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+                upload_id: upload_id.as_bytes().to_vec(),
+            })
+            .await
+            .map_err(|_| StorageClientError::AbortUploadError)?;
 
         Ok(())
     }
 
     pub async fn read_small_file_last_version(
         &self,
+        owner: Option<Uuid>,
         filename: &str,
     ) -> Result<Vec<u8>, StorageClientError> {
         let mut main_server_client = MainServerServiceApiClient::connect(format!(
@@ -178,44 +348,22 @@ impl StorageClient {
             .get_last_version_small_file(GetSmallFileLastVersionRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
             })
             .await
             .map_err(|_| StorageClientError::ReadSmallFileError)?
             .into_inner();
 
-        let mut data_node_client =
-            DataNodeServiceApiClient::connect(format!("http://{}", remote_file.endpoint))
-                .await
-                .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
-
-        let mut data = vec![];
-
-        let response = data_node_client
-            .read_block(ReadBlockRequest {
-                part: remote_file.part,
-                block_id: remote_file.block_id,
-            })
-            .await;
-
-        if let Ok(stream) = response {
-            let mut stream = stream.into_inner();
-            while let Some(part) = stream.next().await {
-                if let Ok(part) = part {
-                    data.push(part.data);
-                } else {
-                    return Err(StorageClientError::ReadSmallFileError);
-                }
-            }
-
-            Ok(data.into_iter().flatten().collect())
-        } else {
-            Err(StorageClientError::ReadSmallFileError)
-        }
+        self.read_block(&remote_file, None, || {
+            StorageClientError::ReadSmallFileError
+        })
+        .await
     }
 
     pub async fn read_small_file(
         &self,
+        owner: Option<Uuid>,
         filename: &str,
         version: usize,
     ) -> Result<Vec<u8>, StorageClientError> {
@@ -230,44 +378,93 @@ impl StorageClient {
             .get_small_file(GetSmallFileRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
                 index: version as u64,
             })
             .await
             .map_err(|_| StorageClientError::ReadSmallFileError)?
             .into_inner();
 
-        let mut data_node_client =
-            DataNodeServiceApiClient::connect(format!("http://{}", remote_file.endpoint))
-                .await
-                .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
+        self.read_block(&remote_file, None, || {
+            StorageClientError::ReadSmallFileError
+        })
+        .await
+    }
 
-        let mut data = vec![];
+    pub async fn read_large_file(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+    ) -> Result<Vec<u8>, StorageClientError> {
+        let blocks = self.get_large_file_blocks(owner, filename).await?;
 
-        let response = data_node_client
-            .read_block(ReadBlockRequest {
-                part: remote_file.part,
-                block_id: remote_file.block_id,
+        let data = futures::stream::iter(blocks)
+            .map(|block| async move {
+                self.read_block(&block, None, || StorageClientError::ReadLargeFileError)
+                    .await
             })
-            .await;
+            .buffered(self.config.max_transfers.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
 
-        if let Ok(stream) = response {
-            let mut stream = stream.into_inner();
-            while let Some(part) = stream.next().await {
-                if let Ok(part) = part {
-                    data.push(part.data);
-                } else {
-                    return Err(StorageClientError::ReadSmallFileError);
+        Ok(data.into_iter().flatten().collect())
+    }
+
+    /// Streams a large file in `read_buffer` sized chunks. Up to `max_transfers`
+    /// blocks are fetched ahead and verified against their checksums before they
+    /// are yielded in part order.
+    pub async fn stream_large_file(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes, StorageClientError>> + '_, StorageClientError>
+    {
+        let blocks = self.get_large_file_blocks(owner, filename).await?;
+        let read_buffer = self.config.read_buffer;
+
+        Ok(futures::stream::iter(blocks)
+            .map(move |block| async move {
+                self.read_block(&block, None, || StorageClientError::ReadLargeFileError)
+                    .await
+            })
+            .buffered(self.config.max_transfers.max(1))
+            .map_ok(move |data| {
+                let mut data = Bytes::from(data);
+                let mut chunks = vec![];
+                while !data.is_empty() {
+                    chunks.push(Ok(data.split_to(read_buffer.min(data.len()))));
                 }
-            }
 
-            Ok(data.into_iter().flatten().collect())
-        } else {
-            Err(StorageClientError::ReadSmallFileError)
-        }
+                futures::stream::iter(chunks)
+            })
+            .try_flatten())
     }
 
-    pub async fn read_large_file(&self, filename: &str) -> Result<Vec<u8>, StorageClientError> {
+    /// Same as `stream_large_file`, but exposes the file as `AsyncRead`.
+    pub async fn large_file_reader(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+    ) -> Result<impl AsyncRead + Unpin + '_, StorageClientError> {
+        let stream = self
+            .stream_large_file(owner, filename)
+            .await?
+            .map_err(|err| std::io::Error::other(format!("{:?}", err)));
+
+        Ok(StreamReader::new(Box::pin(stream)))
+    }
+
+    /// Reads `len` bytes from `offset` of a large file or of the last version of a
+    /// small file, fetching only the blocks covering the range. The result is
+    /// shorter than `len` if the range crosses the end of the file.
+    pub async fn read_range(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, StorageClientError> {
         let mut main_server_client = MainServerServiceApiClient::connect(format!(
             "http://{}",
             self.config.main_server_address
@@ -275,53 +472,81 @@ impl StorageClient {
         .await
         .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
 
-        let remote_file = main_server_client
+        let response = main_server_client
             .get_large_file(GetLargeFileRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
             })
-            .await
-            .map_err(|_| StorageClientError::ReadSmallFileError)?
-            .into_inner();
+            .await;
 
-        let count = remote_file.blocks.len();
-        let mut data: Vec<Vec<u8>> = (0..count).map(|_| vec![]).collect();
+        let mut block_size = self.config.block_size;
+        let mut blocks = match response {
+            Ok(remote_file) => {
+                let remote_file = remote_file.into_inner();
+                // Main servers which do not report it use the configured one
+                if remote_file.block_size != 0 {
+                    block_size = remote_file.block_size as usize;
+                }
+                remote_file.blocks
+            }
+            Err(status) => match MetadataError::try_from(status) {
+                Ok(MetadataError::TryingToGetLargeButItSmall(_)) => {
+                    let block = main_server_client
+                        .get_last_version_small_file(GetSmallFileLastVersionRequest {
+                            filename: filename.to_string(),
+                            user_id: self.config.client_id.to_bytes_le().to_vec(),
+                            group_ids: self.group_ids(),
+                            owner_id: owner_id(owner),
+                        })
+                        .await
+                        .map_err(|_| StorageClientError::ReadSmallFileError)?
+                        .into_inner();
 
-        for block in remote_file.blocks {
-            let mut data_node_client =
-                DataNodeServiceApiClient::connect(format!("http://{}", block.endpoint))
-                    .await
-                    .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
+                    vec![block]
+                }
+                _ => return Err(StorageClientError::ReadLargeFileError),
+            },
+        };
+        blocks.sort_by_key(|block| block.part);
 
-            let response = data_node_client
-                .read_block(ReadBlockRequest {
-                    part: block.part,
-                    block_id: block.block_id,
-                })
-                .await;
+        let end = offset + len;
+        let mut data = Vec::with_capacity(len);
 
-            let mut local_data = vec![];
+        for (part, block) in blocks
+            .iter()
+            .enumerate()
+            .skip(offset / block_size)
+            .take_while(|(part, _)| part * block_size < end)
+        {
+            let block_start = part * block_size;
+            let range = Range {
+                start: (offset.max(block_start) - block_start) as u64,
+                end: (end.min(block_start + block_size) - block_start) as u64,
+            };
 
-            if let Ok(stream) = response {
-                let mut stream = stream.into_inner();
-                while let Some(part) = stream.next().await {
-                    if let Ok(part) = part {
-                        local_data.push(part.data);
-                    } else {
-                        return Err(StorageClientError::ReadLargeFileError);
-                    }
-                }
-                data[block.part as usize] = local_data.into_iter().flatten().collect()
-            } else {
-                return Err(StorageClientError::ReadLargeFileError);
+            let requested = (range.end - range.start) as usize;
+
+            let chunk = self
+                .read_block(block, Some(range), || StorageClientError::ReadRangeError)
+                .await?;
+            let short_read = chunk.len() < requested;
+            data.extend(chunk);
+
+            if short_read {
+                break;
             }
         }
 
-        Ok(data.into_iter().flatten().collect())
+        Ok(data)
     }
 
-    pub async fn delete_file(&self, filename: &str) -> Result<(), StorageClientError> {
+    pub async fn delete_file(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+    ) -> Result<(), StorageClientError> {
         let mut main_server_client = MainServerServiceApiClient::connect(format!(
             "http://{}",
             self.config.main_server_address
@@ -333,7 +558,9 @@ impl StorageClient {
             .delete_file(DeleteFileRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+                version: 0,
             })
             .await
             .map_err(|_| StorageClientError::DeleteFileError)?;
@@ -341,8 +568,65 @@ impl StorageClient {
         Ok(())
     }
 
+    /// Shares the file with members of `group_id`, replacing the permissions the
+    /// group had before.
+    pub async fn grant_access(
+        &self,
+        filename: &str,
+        group_id: Uuid,
+        permissions: Permissions,
+    ) -> Result<(), StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
+
+        main_server_client
+            .grant_access(GrantAccessRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_id: group_id.to_bytes_le().to_vec(),
+                permissions: Some(GroupPermissions {
+                    read: permissions.read,
+                    write: permissions.write,
+                    delete: permissions.delete,
+                }),
+            })
+            .await
+            .map_err(|_| StorageClientError::ShareFileError)?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_access(
+        &self,
+        filename: &str,
+        group_id: Uuid,
+    ) -> Result<(), StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
+
+        main_server_client
+            .revoke_access(RevokeAccessRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_id: group_id.to_bytes_le().to_vec(),
+            })
+            .await
+            .map_err(|_| StorageClientError::ShareFileError)?;
+
+        Ok(())
+    }
+
     pub async fn add_new_commit_to_small_file(
         &self,
+        owner: Option<Uuid>,
         filename: &str,
         data: &[u8],
     ) -> Result<(), StorageClientError> {
@@ -357,44 +641,53 @@ impl StorageClient {
             .add_commit_to_small_file(AddCommitSmallFileRequest {
                 filename: filename.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
-                group_ids: vec![],
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+                version: 0,
             })
             .await
             .map_err(|_| StorageClientError::AddNewCommitToSmallFileError)?
             .into_inner();
 
-        let mut data_node_client =
-            DataNodeServiceApiClient::connect(format!("http://{}", block.endpoint))
-                .await
-                .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
-
-        let update_info = UpdateBlockRequest {
-            filename: filename.to_string(),
-            block_id: block.block_id.clone(),
-            part: block.part,
-            range: Some(Range {
-                start: 0,
-                end: data.len() as u64,
-            }),
-            data: data.to_vec(),
-            hash: 0,
-        };
+        let update_info = self.update_requests(&block, data)?;
 
-        let stream = tokio_stream::iter(std::iter::once(update_info));
-
-        let _ = data_node_client
-            .update_block(stream)
-            .await
-            .map_err(|_| StorageClientError::UpdateBlockError)?
-            .into_inner();
+        self.write_block(&block, update_info).await?;
 
         Ok(())
     }
 
+    /// Lists all files starting with `prefix`, page by page.
     pub async fn get_files(
         &self,
+        owner: Option<Uuid>,
         prefix: &str,
     ) -> Result<Vec<(String, ObjectType)>, StorageClientError> {
+        let mut files = vec![];
+        let mut continuation_token = None;
+
+        loop {
+            let page = self
+                .list_files(owner, prefix, "", continuation_token.as_deref(), 0)
+                .await?;
+            files.extend(page.files);
+
+            match page.continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok(files),
+            }
+        }
+    }
+
+    /// Returns a page of up to `page_size` files starting with `prefix`. Files with
+    /// `delimiter` after the prefix are returned once as their common prefix.
+    pub async fn list_files(
+        &self,
+        owner: Option<Uuid>,
+        prefix: &str,
+        delimiter: &str,
+        continuation_token: Option<&str>,
+        page_size: u32,
+    ) -> Result<FileListing, StorageClientError> {
         let mut main_server_client = MainServerServiceApiClient::connect(format!(
             "http://{}",
             self.config.main_server_address
@@ -406,13 +699,18 @@ impl StorageClient {
             .get_files(FileRequest {
                 prefix: prefix.to_string(),
                 user_id: self.config.client_id.to_bytes_le().to_vec(),
+                page_size,
+                continuation_token: continuation_token.unwrap_or_default().to_string(),
+                delimiter: delimiter.to_string(),
+                owner_id: owner_id(owner),
+                group_ids: self.group_ids(),
             })
             .await
-            .unwrap()
-            .into_inner()
-            .files;
+            .map_err(|_| StorageClientError::ListFilesError)?
+            .into_inner();
 
-        Ok(response
+        let files = response
+            .files
             .into_iter()
             .map(|el| {
                 let ty = match el.r#type {
@@ -423,8 +721,295 @@ impl StorageClient {
 
                 (el.filename, ty)
             })
+            .collect();
+
+        Ok(FileListing {
+            files,
+            prefixes: response.common_prefixes,
+            continuation_token: Some(response.next_continuation_token)
+                .filter(|token| !token.is_empty()),
+        })
+    }
+
+    /// Returns a pooled connection to the data node, connecting on first use.
+    async fn data_node_client(
+        &self,
+        endpoint: &str,
+    ) -> Result<DataNodeServiceApiClient<Channel>, StorageClientError> {
+        if let Some(client) = self.data_nodes.read().await.get(endpoint) {
+            return Ok(client.clone());
+        }
+
+        let client = DataNodeServiceApiClient::connect(format!("http://{}", endpoint))
+            .await
+            .map_err(|_| StorageClientError::WrongDatanodeAddressError)?;
+        self.data_nodes
+            .write()
+            .await
+            .insert(endpoint.to_string(), client.clone());
+
+        Ok(client)
+    }
+
+    async fn start_multipart_upload(
+        &self,
+        filename: &str,
+        share_with: &[Uuid],
+        size: u64,
+    ) -> Result<(Uuid, Vec<BlockInfo>), StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
+
+        let response = main_server_client
+            .initiate_multipart_upload(CreateFileRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_ids: self.group_ids(),
+                size,
+                hash_algorithm: self.config.hash_algorithm.into(),
+                share_with_group_ids: group_ids(share_with),
+            })
+            .await
+            .map_err(|_| StorageClientError::CreateFileError)?
+            .into_inner();
+
+        let upload_id = Uuid::from_slice(&response.upload_id)
+            .map_err(|_| StorageClientError::CreateFileError)?;
+
+        Ok((upload_id, response.blocks))
+    }
+
+    /// Gets blocks of a large file ordered by part and checks them against the root
+    /// of the Merkle tree.
+    async fn get_large_file_blocks(
+        &self,
+        owner: Option<Uuid>,
+        filename: &str,
+    ) -> Result<Vec<BlockInfo>, StorageClientError> {
+        let mut main_server_client = MainServerServiceApiClient::connect(format!(
+            "http://{}",
+            self.config.main_server_address
+        ))
+        .await
+        .map_err(|_| StorageClientError::WrongMetadataAddressError)?;
+
+        let remote_file = main_server_client
+            .get_large_file(GetLargeFileRequest {
+                filename: filename.to_string(),
+                user_id: self.config.client_id.to_bytes_le().to_vec(),
+                group_ids: self.group_ids(),
+                owner_id: owner_id(owner),
+            })
+            .await
+            .map_err(|_| StorageClientError::ReadLargeFileError)?
+            .into_inner();
+
+        let hash_algorithm = HashAlgorithm::try_from(remote_file.hash_algorithm)
+            .map_err(|_| StorageClientError::UnknownHashAlgorithmError)?;
+        let checksums = remote_file
+            .blocks
+            .iter()
+            .map(|block| block.checksum.clone())
+            .collect::<Vec<_>>();
+        if merkle_root(&checksums, hash_algorithm) != Some(remote_file.root) {
+            return Err(StorageClientError::IntegrityError);
+        }
+
+        let mut blocks = remote_file.blocks;
+        blocks.sort_by_key(|block| block.part);
+
+        Ok(blocks)
+    }
+
+    /// Groups the client is a member of, sent with every request to a file.
+    fn group_ids(&self) -> Vec<Vec<u8>> {
+        group_ids(&self.config.group_ids)
+    }
+
+    /// Splits the data of a block into `read_buffer` sized update messages.
+    fn update_requests(
+        &self,
+        block: &BlockInfo,
+        data: &[u8],
+    ) -> Result<Vec<UpdateBlockRequest>, StorageClientError> {
+        let hash_algorithm = block_hash_algorithm(block)?;
+        let block_hash = hash_algorithm.hash(data);
+
+        // An empty block still needs one message to get its checksum recorded
+        let chunks = if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(self.config.read_buffer).collect()
+        };
+
+        let mut offset = 0;
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| {
+                let range = Range {
+                    start: offset as u64,
+                    end: (offset + chunk.len()) as u64,
+                };
+                offset += chunk.len();
+
+                UpdateBlockRequest {
+                    filename: block.object_key.clone(),
+                    block_id: block.block_id.clone(),
+                    part: block.part,
+                    data: chunk.to_vec(),
+                    range: Some(range),
+                    hash: hash_algorithm.hash(chunk),
+                    block_hash: block_hash.clone(),
+                    hash_algorithm: hash_algorithm.into(),
+                }
+            })
             .collect())
     }
+
+    /// Writes the same data to the primary data node and every replica of the block.
+    /// Returns the checksum confirmed by all of them.
+    async fn write_block(
+        &self,
+        block: &BlockInfo,
+        update_info: Vec<UpdateBlockRequest>,
+    ) -> Result<Checksum, StorageClientError> {
+        let block_hash = update_info
+            .first()
+            .map(|update_info| update_info.block_hash.clone())
+            .unwrap_or_default();
+
+        let tasks = std::iter::once(&block.endpoint)
+            .chain(block.replicas.iter())
+            .map(|endpoint| {
+                let update_info = update_info.clone();
+                let block_hash = block_hash.clone();
+                async move {
+                    let mut data_node_client = self.data_node_client(endpoint).await?;
+
+                    let stream = tokio_stream::iter(update_info);
+
+                    let response = data_node_client
+                        .update_block(stream)
+                        .await
+                        .map_err(|_| StorageClientError::UpdateBlockError)?
+                        .into_inner();
+
+                    if response.checksum != block_hash {
+                        return Err(StorageClientError::ChecksumMismatchError);
+                    }
+
+                    Ok::<_, StorageClientError>(())
+                }
+            });
+
+        futures::future::try_join_all(tasks).await?;
+
+        Ok(block_hash)
+    }
+
+    /// Reads the block or its `range` from the primary data node and falls back to
+    /// the replicas in order when it is unreachable or returns data not matching
+    /// the stored checksum. Only whole blocks can be checked against the checksum.
+    /// Returns the error of the last tried node.
+    async fn read_block(
+        &self,
+        block: &BlockInfo,
+        range: Option<Range>,
+        read_error: fn() -> StorageClientError,
+    ) -> Result<Vec<u8>, StorageClientError> {
+        let hash_algorithm = block_hash_algorithm(block)?;
+        let mut last_error = read_error();
+
+        for endpoint in std::iter::once(&block.endpoint).chain(block.replicas.iter()) {
+            let Ok(mut data_node_client) = self.data_node_client(endpoint).await else {
+                last_error = StorageClientError::WrongDatanodeAddressError;
+                continue;
+            };
+
+            let response = data_node_client
+                .read_block(ReadBlockRequest {
+                    part: block.part,
+                    block_id: block.block_id.clone(),
+                    range: range.clone(),
+                })
+                .await;
+
+            let Ok(stream) = response else {
+                last_error = read_error();
+                continue;
+            };
+
+            let mut data = vec![];
+            let mut stream = stream.into_inner();
+            let mut failed = false;
+            while let Some(part) = stream.next().await {
+                if let Ok(part) = part {
+                    data.push(part.data);
+                } else {
+                    failed = true;
+                    break;
+                }
+            }
+
+            if failed {
+                last_error = read_error();
+                continue;
+            }
+
+            let data = data.into_iter().flatten().collect::<Vec<u8>>();
+            if range.is_none() && hash_algorithm.hash(&data) != block.checksum {
+                last_error = StorageClientError::IntegrityError;
+                continue;
+            }
+
+            return Ok(data);
+        }
+
+        Err(last_error)
+    }
+}
+
+/// Owner of the addressed file, sent empty for files of the client itself.
+fn owner_id(owner: Option<Uuid>) -> Vec<u8> {
+    owner
+        .map(|owner| owner.to_bytes_le().to_vec())
+        .unwrap_or_default()
+}
+
+fn group_ids(group_ids: &[Uuid]) -> Vec<Vec<u8>> {
+    group_ids
+        .iter()
+        .map(|group_id| group_id.to_bytes_le().to_vec())
+        .collect()
+}
+
+fn block_hash_algorithm(block: &BlockInfo) -> Result<HashAlgorithm, StorageClientError> {
+    HashAlgorithm::try_from(block.hash_algorithm)
+        .map_err(|_| StorageClientError::UnknownHashAlgorithmError)
+}
+
+/// Recomputes the root of the Merkle tree the main server keeps over block checksums.
+fn merkle_root(checksums: &[Checksum], hash_algorithm: HashAlgorithm) -> Option<Checksum> {
+    let mut level = checksums.to_vec();
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|chunk| {
+                if chunk.len() == 2 {
+                    hash_algorithm.combine(&chunk[0], &chunk[1])
+                } else {
+                    chunk[0].clone()
+                }
+            })
+            .collect();
+    }
+
+    level.into_iter().next()
 }
 
 #[derive(Debug)]
@@ -435,6 +1020,17 @@ pub enum StorageClientError {
     UpdateBlockError,
     ReadSmallFileError,
     ReadLargeFileError,
+    ReadRangeError,
+    ReadInputError,
     DeleteFileError,
     AddNewCommitToSmallFileError,
+    ChecksumMismatchError,
+    IntegrityError,
+    UnknownHashAlgorithmError,
+    UploadPartError,
+    ListUploadedPartsError,
+    CompleteUploadError,
+    AbortUploadError,
+    ListFilesError,
+    ShareFileError,
 }
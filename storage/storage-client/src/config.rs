@@ -1,5 +1,6 @@
 use clap::Parser;
 use serde::Deserialize;
+use shared::hash::HashAlgorithm;
 use std::path::Path;
 use tokio::io::AsyncReadExt;
 use uuid::Uuid;
@@ -16,6 +17,22 @@ pub struct Config {
     pub(crate) block_size: usize,
     /// Идентификатор пользователя
     pub(crate) client_id: Uuid,
+    /// Groups the user is a member of, they give access to files of other users
+    #[arg(long)]
+    #[serde(default)]
+    pub(crate) group_ids: Vec<Uuid>,
+    /// Hash algorithm of checksums for new files: crc32, sha256 or blake3
+    #[arg(long, default_value = "crc32")]
+    #[serde(default)]
+    pub(crate) hash_algorithm: HashAlgorithm,
+    /// Count of blocks transferred to or from data nodes at once
+    #[arg(long, default_value_t = 4)]
+    #[serde(default = "default_max_transfers")]
+    pub(crate) max_transfers: usize,
+}
+
+fn default_max_transfers() -> usize {
+    4
 }
 
 impl Config {
@@ -39,6 +56,12 @@ impl Config {
         Ok(config)
     }
 
+    /// Replaces the groups the user is a member of.
+    pub fn with_group_ids(mut self, group_ids: Vec<Uuid>) -> Self {
+        self.group_ids = group_ids;
+        self
+    }
+
     pub fn get_main_server_addr(&self) -> &str {
         &self.main_server_address
     }
@@ -5,6 +5,7 @@ pub mod config;
 mod tests {
     use crate::client::StorageClient;
     use crate::config::Config;
+    use tokio::io::AsyncReadExt;
     use uuid::Uuid;
 
     #[tokio::test]
@@ -13,7 +14,7 @@ mod tests {
         let client = StorageClient::new(config);
         let file = tokio::fs::File::open("../../Cargo.lock").await.unwrap();
         client
-            .create_small_file("test", file)
+            .create_small_file("test", &[], file)
             .await
             .unwrap()
     }
@@ -24,7 +25,7 @@ mod tests {
         let client = StorageClient::new(config);
         let file = tokio::fs::File::open("../../CargoBig.lock").await.unwrap();
         client
-            .create_large_file("test_big", file)
+            .create_large_file("test_big", &[], file)
             .await
             .unwrap()
     }
@@ -34,7 +35,7 @@ mod tests {
         let config = Config::try_from_file("../../ClientTest.toml").await;
         let client = StorageClient::new(config);
         let data = client
-            .read_small_file_last_version("test")
+            .read_small_file_last_version(None, "test")
             .await
             .unwrap();
 
@@ -46,18 +47,92 @@ mod tests {
         let config = Config::try_from_file("../../ClientTest.toml").await;
         let client = StorageClient::new(config);
         let data = client
-            .read_large_file("test_big")
+            .read_large_file(None, "test_big")
             .await
             .unwrap();
 
         assert_eq!(164820, data.len());
     }
 
+    #[tokio::test]
+    async fn stream_large_file_test() {
+        let config = Config::try_from_file("../../ClientTest.toml").await;
+        let client = StorageClient::new(config);
+        let mut reader = client
+            .large_file_reader(None, "test_big")
+            .await
+            .unwrap();
+
+        let mut data = vec![];
+        reader.read_to_end(&mut data).await.unwrap();
+
+        assert_eq!(164820, data.len());
+    }
+
+    #[tokio::test]
+    async fn read_range_test() {
+        let config = Config::try_from_file("../../ClientTest.toml").await;
+        let client = StorageClient::new(config);
+        let data = client
+            .read_large_file(None, "test_big")
+            .await
+            .unwrap();
+        let range = client
+            .read_range(None, "test_big", 65000, 1000)
+            .await
+            .unwrap();
+
+        assert_eq!(&data[65000..66000], &range[..]);
+    }
+
+    #[tokio::test]
+    async fn multipart_upload_test() {
+        let config = Config::try_from_file("../../ClientTest.toml").await;
+        let client = StorageClient::new(config);
+        let data = vec![7u8; 100000];
+        let upload_id = client
+            .initiate_multipart_upload("test_multipart", &[], data.len() as u64)
+            .await
+            .unwrap();
+
+        let first = client
+            .upload_part(None, "test_multipart", upload_id, 0, &data[..65536])
+            .await
+            .unwrap();
+        let uploaded = client
+            .list_uploaded_parts(None, "test_multipart", upload_id)
+            .await
+            .unwrap();
+        assert_eq!(vec![(0, first.clone())], uploaded);
+
+        let second = client
+            .upload_part(None, "test_multipart", upload_id, 1, &data[65536..])
+            .await
+            .unwrap();
+        client
+            .complete_multipart_upload(
+                None,
+                "test_multipart",
+                upload_id,
+                &[(0, first), (1, second)],
+            )
+            .await
+            .unwrap();
+
+        let read = client
+            .read_large_file(None, "test_multipart")
+            .await
+            .unwrap();
+        client.delete_file(None, "test_multipart").await.unwrap();
+
+        assert_eq!(data, read);
+    }
+
     #[tokio::test]
     async fn delete_file_test() {
         let config = Config::try_from_file("../../ClientTest.toml").await;
         let client = StorageClient::new(config);
-        let _ = client.delete_file("test").await.unwrap();
+        client.delete_file(None, "test").await.unwrap();
     }
 
     #[tokio::test]
@@ -66,12 +141,13 @@ mod tests {
         let client = StorageClient::new(config);
         let file = tokio::fs::File::open("../../Cargo.lock").await.unwrap();
         client
-            .create_small_file("test", file)
+            .create_small_file("test", &[], file)
             .await
             .unwrap();
 
         client
             .add_new_commit_to_small_file(
+                None,
                 "test",
                 &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
             )
@@ -79,11 +155,11 @@ mod tests {
             .unwrap();
 
         let data = client
-            .read_small_file_last_version( "test")
+            .read_small_file_last_version( None, "test")
             .await
             .unwrap();
 
-        let _ = client.delete_file("test").await.unwrap();
+        client.delete_file(None, "test").await.unwrap();
 
         assert_eq!(16, data.len());
     }